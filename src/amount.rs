@@ -0,0 +1,274 @@
+//! # Amount utilities.
+
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// The default number of decimals of the amounts.
+pub const DEFAULT_PRECISION: u32 = 4;
+
+/// The maximum number of decimals of the amounts.
+pub const MAX_PRECISION: u32 = 8;
+
+/// Number of units in 1.0.
+const SCALE: i64 = 10i64.pow(MAX_PRECISION);
+
+/// The modes in which the amounts are rounded to fewer decimals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RoundingMode {
+    /// Drop the extra decimals, rounding towards zero.
+    #[default]
+    Truncate,
+    /// Round to the nearest, with halves away from zero.
+    HalfUp,
+    /// Round to the nearest, with halves to the even neighbour, i.e. banker's rounding.
+    HalfEven,
+}
+
+/// A fixed point amount with [`MAX_PRECISION`] decimals.
+///
+/// The amount is stored as an integer number of units of `10^-MAX_PRECISION` so that the balances
+/// are exact, and all the arithmetic is checked or saturating instead of overflowing.
+///
+/// ```
+/// use payments_engine_rs::Amount;
+/// let amount: Amount = "1.5".parse().unwrap();
+/// assert_eq!(amount.checked_add(amount), Some("3.0".parse().unwrap()));
+/// assert_eq!(amount.to_string(), "1.5");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Amount(i64);
+
+impl Amount {
+    pub const ZERO: Amount = Amount(0);
+    pub const MAX: Amount = Amount(i64::MAX);
+
+    /// Create an amount from its number of units of `10^-MAX_PRECISION`.
+    pub const fn from_units(units: i64) -> Amount {
+        Amount(units)
+    }
+
+    /// The number of units of `10^-MAX_PRECISION` of the amount.
+    pub const fn units(self) -> i64 {
+        self.0
+    }
+
+    pub fn checked_add(self, other: Amount) -> Option<Amount> {
+        self.0.checked_add(other.0).map(Amount)
+    }
+
+    pub fn checked_sub(self, other: Amount) -> Option<Amount> {
+        self.0.checked_sub(other.0).map(Amount)
+    }
+
+    pub fn saturating_add(self, other: Amount) -> Amount {
+        Amount(self.0.saturating_add(other.0))
+    }
+
+    pub fn saturating_sub(self, other: Amount) -> Amount {
+        Amount(self.0.saturating_sub(other.0))
+    }
+
+    /// Truncate the amount to the given number of decimals.
+    pub fn truncate(self, precision: u32) -> Amount {
+        match precision {
+            precision if precision >= MAX_PRECISION => self,
+            precision => {
+                let unit = 10i64.pow(MAX_PRECISION - precision);
+                Amount(self.0 / unit * unit)
+            }
+        }
+    }
+
+    /// Round the amount to the given number of decimals, saturating at the representable bounds.
+    pub fn round(self, precision: u32, rounding_mode: RoundingMode) -> Amount {
+        if precision >= MAX_PRECISION {
+            return self;
+        }
+        let unit = 10i64.pow(MAX_PRECISION - precision);
+        let (quotient, remainder) = (self.0 / unit, (self.0 % unit).abs());
+        let round_away = match rounding_mode {
+            RoundingMode::Truncate => false,
+            RoundingMode::HalfUp => remainder * 2 >= unit,
+            RoundingMode::HalfEven => remainder * 2 > unit || (remainder * 2 == unit && quotient % 2 != 0),
+        };
+        let quotient = if round_away { quotient + self.0.signum() } else { quotient };
+        Amount(quotient.saturating_mul(unit))
+    }
+
+    /// Format the amount truncated to exactly the given number of decimals.
+    pub fn format(self, precision: u32) -> String {
+        let precision = precision.min(MAX_PRECISION);
+        let truncated = self.truncate(precision);
+        let sign = if truncated.0 < 0 { "-" } else { "" };
+        let units = truncated.0.unsigned_abs();
+        let whole = units / SCALE as u64;
+        if precision == 0 {
+            return format!("{}{}", sign, whole);
+        }
+        let decimals = format!("{:0width$}", units % SCALE as u64, width = MAX_PRECISION as usize);
+        format!("{}{}.{}", sign, whole, &decimals[..precision as usize])
+    }
+}
+
+/// Formats the amount with its significant decimals, and at least one, e.g. `1.0` or `1.2345`, or
+/// with exactly the decimals of the precision of the formatter, e.g. `{:.4}` formats `1.8888` or
+/// `0.0000`, see [`Amount::format`].
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(precision) = f.precision() {
+            return f.write_str(&self.format(precision.min(MAX_PRECISION as usize) as u32));
+        }
+        let formatted = self.format(MAX_PRECISION);
+        let trimmed = formatted.trim_end_matches('0');
+        match trimmed.strip_suffix('.') {
+            Some(whole) => write!(f, "{}.0", whole),
+            None => write!(f, "{}", trimmed),
+        }
+    }
+}
+
+/// The error returned when an amount can't be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseAmountError(pub(crate) String);
+
+impl Error for ParseAmountError {}
+
+impl fmt::Display for ParseAmountError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid amount {}", self.0)
+    }
+}
+
+/// Parses a decimal amount, e.g. `-1.5`, truncating the decimals beyond [`MAX_PRECISION`].
+impl FromStr for Amount {
+    type Err = ParseAmountError;
+
+    fn from_str(amount: &str) -> Result<Amount, ParseAmountError> {
+        let err = || ParseAmountError(amount.to_string());
+        let (negative, unsigned) = match amount.strip_prefix('-') {
+            Some(unsigned) => (true, unsigned),
+            None => (false, amount.strip_prefix('+').unwrap_or(amount)),
+        };
+        let (whole, decimals) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+        if whole.is_empty() && decimals.is_empty()
+            || !whole.bytes().chain(decimals.bytes()).all(|byte| byte.is_ascii_digit()) {
+            return Err(err());
+        }
+
+        // The extra decimals are ignored and the missing ones are zeros.
+        let decimals = &decimals[..decimals.len().min(MAX_PRECISION as usize)];
+        let mut units: i64 = 0;
+        for digit in whole.bytes().chain(decimals.bytes()) {
+            units = units.checked_mul(10)
+                .and_then(|units| units.checked_add((digit - b'0') as i64))
+                .ok_or_else(err)?;
+        }
+        units = units.checked_mul(10i64.pow(MAX_PRECISION - decimals.len() as u32)).ok_or_else(err)?;
+        Ok(Amount(if negative { -units } else { units }))
+    }
+}
+
+/// Serializes the amount as a string to keep its exact decimal representation.
+impl Serialize for Amount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Amount, D::Error> {
+        let amount = String::deserialize(deserializer)?;
+        amount.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Parse a decimal amount truncated to the given number of decimals.
+pub(crate) fn parse(amount: &str, precision: u32) -> Result<Amount, ParseAmountError> {
+    amount.parse::<Amount>().map(|amount| amount.truncate(precision))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn amount(amount: &str) -> Amount {
+        amount.parse().unwrap()
+    }
+
+    #[test]
+    fn truncate_to_precision() {
+        assert_eq!(amount("1.99999").truncate(4), amount("1.9999"));
+        assert_eq!(amount("1.99999").truncate(2), amount("1.99"));
+        assert_eq!(amount("-1.99999").truncate(0), amount("-1.0"));
+        assert_eq!(amount("0.009").truncate(8), amount("0.009"));
+        assert_eq!(amount("1.9999").checked_sub(amount("0.1111")).unwrap().truncate(4), amount("1.8888"));
+    }
+
+    #[test]
+    fn parse_to_precision() {
+        assert_eq!(parse("1.123456789", 4).unwrap(), amount("1.1234"));
+        assert_eq!(parse("1.123456789", 8).unwrap(), Amount::from_units(112_345_678));
+        assert_eq!(parse("0.0003", 4).unwrap(), Amount::from_units(30_000));
+        assert_eq!(parse("1.5", 0).unwrap(), amount("1"));
+        assert_eq!(parse("2", 4).unwrap(), Amount::from_units(200_000_000));
+        assert_eq!(parse("-5.0", 4).unwrap(), Amount::from_units(-500_000_000));
+        assert_eq!(parse(".5", 4).unwrap(), amount("0.5"));
+        for invalid in ["NaN", "inf", "1.a", "1e3", "", ".", "-", "1.2.3", "99999999999999999999"] {
+            assert!(parse(invalid, 4).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn round_to_precision() {
+        assert_eq!(amount("1.99995").round(4, RoundingMode::Truncate), amount("1.9999"));
+        assert_eq!(amount("2.00005").round(4, RoundingMode::Truncate), amount("2.0"));
+        assert_eq!(amount("1.99995").round(4, RoundingMode::HalfUp), amount("2.0"));
+        assert_eq!(amount("2.00005").round(4, RoundingMode::HalfUp), amount("2.0001"));
+        assert_eq!(amount("1.99995").round(4, RoundingMode::HalfEven), amount("2.0"));
+        assert_eq!(amount("2.00005").round(4, RoundingMode::HalfEven), amount("2.0"));
+        assert_eq!(amount("-2.00015").round(4, RoundingMode::HalfEven), amount("-2.0002"));
+        assert_eq!(amount("-2.00005").round(4, RoundingMode::HalfUp), amount("-2.0001"));
+        assert_eq!(amount("2.000049").round(4, RoundingMode::HalfUp), amount("2.0"));
+        assert_eq!(Amount::MAX.round(0, RoundingMode::HalfUp), Amount::MAX);
+    }
+
+    #[test]
+    fn format_amount() {
+        assert_eq!(amount("1.5").format(4), "1.5000");
+        assert_eq!(amount("1.99999").format(2), "1.99");
+        assert_eq!(amount("-0.5").format(0), "0");
+        assert_eq!(amount("-10").format(4), "-10.0000");
+        assert_eq!(amount("1.5").to_string(), "1.5");
+        assert_eq!(amount("-10").to_string(), "-10.0");
+        assert_eq!(Amount::ZERO.to_string(), "0.0");
+    }
+
+    #[test]
+    fn display_amount_with_precision() {
+        assert_eq!(format!("{:.4}", Amount::ZERO), "0.0000");
+        assert_eq!(format!("{:.4}", amount("1.88889")), "1.8888");
+        assert_eq!(format!("{:.4}", amount("-1.5")), "-1.5000");
+        assert_eq!(format!("{:.0}", amount("-1.5")), "-1");
+        assert_eq!(format!("{:.4}", Amount::MAX), "92233720368.5477");
+        assert_eq!(format!("{:.12}", Amount::MAX), "92233720368.54775807");
+        assert_eq!(format!("{:.4}", Amount::from_units(-i64::MAX)), "-92233720368.5477");
+        assert_eq!(format!("{:.*}", 2, amount("3")), "3.00");
+        assert_eq!(amount(&format!("{:.4}", amount("-7.25"))), amount("-7.25"));
+    }
+
+    #[test]
+    fn serialize_amount() {
+        assert_eq!(serde_json::to_string(&amount("-1.23456789")).unwrap(), "\"-1.23456789\"");
+        assert_eq!(serde_json::from_str::<Amount>("\"1.5\"").unwrap(), amount("1.5"));
+        assert!(serde_json::from_str::<Amount>("\"1.a\"").is_err());
+    }
+
+    #[test]
+    fn checked_arithmetic() {
+        assert_eq!(Amount::MAX.checked_add(amount("1")), None);
+        assert_eq!(Amount::MAX.saturating_add(amount("1")), Amount::MAX);
+        assert_eq!(amount("1").checked_sub(amount("11")), Some(amount("-10")));
+    }
+}
@@ -0,0 +1,37 @@
+//! # Writer computing a checksum of the output.
+
+use std::io;
+use sha2::{Digest, Sha256};
+
+/// Writer computing the SHA-256 checksum of the bytes written to the underlying writer.
+pub struct ChecksumWriter<W: io::Write> {
+    writer: W,
+    hasher: Sha256,
+}
+
+impl<W: io::Write> ChecksumWriter<W> {
+    pub fn new(writer: W) -> ChecksumWriter<W> {
+        ChecksumWriter {
+            writer,
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// Retrieve the underlying writer and the checksum of the bytes written to it.
+    pub fn into_inner(self) -> (W, [u8; 32]) {
+        (self.writer, self.hasher.finalize().into())
+    }
+}
+
+impl<W: io::Write> io::Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Only the bytes accepted by the underlying writer are part of the output.
+        let written = self.writer.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
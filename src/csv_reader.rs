@@ -1,46 +1,436 @@
 //! # CSV Reader utilities for transactions.
 
+use std::borrow::Cow;
 use std::error::Error;
 use std::{fmt, io};
-use csv;
-use csv::Trim;
 use serde::{Deserialize};
-use crate::Transaction;
-use crate::transaction_manager::TransactionType;
+use crate::amount::{self, Amount, ParseAmountError};
+use crate::error::EngineError;
+use crate::{InputFormat, Transaction};
+use crate::transaction_manager::{ClientId, TransactionType};
+
+/// The default maximum length of the csv fields, in bytes.
+pub const DEFAULT_MAX_FIELD_LEN: usize = 1 << 20;
+
+/// The policies to handle the records that can't be parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorPolicy {
+    /// Fail on the first record that can't be parsed.
+    #[default]
+    StrictParse,
+    /// Skip the records that can't be parsed and continue with the next ones.
+    SkipBad,
+}
+
+/// The policies to handle the deposits and withdrawals with an empty amount.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EmptyAmountPolicy {
+    /// The record can't be parsed, and is handled according to the [`ErrorPolicy`].
+    #[default]
+    Error,
+    /// The amount is zero, so the transaction is then ignored by the transaction manager.
+    TreatAsZero,
+    /// The record is skipped, like the records of unknown types with `skip_unknown_types`.
+    Skip,
+}
+
+/// The parts of the csv whose fields are trimmed of their leading and trailing whitespaces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Trim {
+    /// Both the header and the records.
+    #[default]
+    All,
+    /// Only the records.
+    Fields,
+    /// Only the header.
+    Headers,
+    /// Nothing, the whitespaces are significant.
+    None,
+}
+
+impl Trim {
+    fn to_csv(self) -> csv::Trim {
+        match self {
+            Trim::All => csv::Trim::All,
+            Trim::Fields => csv::Trim::Fields,
+            Trim::Headers => csv::Trim::Headers,
+            Trim::None => csv::Trim::None,
+        }
+    }
+}
+
+/// The formats of the amounts in the csv.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AmountFormat {
+    /// A period as the decimal separator, e.g. `1234.50`.
+    #[default]
+    DotDecimal,
+    /// A comma as the decimal separator and optionally periods as the thousands separators between
+    /// groups of three digits, e.g. `1.234,50`. The fields are then delimited by semicolons by
+    /// default.
+    CommaDecimal,
+}
+
+impl AmountFormat {
+    /// Convert the amount to the format with a period as the decimal separator.
+    ///
+    /// With [`AmountFormat::CommaDecimal`], the periods must separate the thousands, so that an
+    /// amount with a period as the decimal separator, e.g. `1.50`, isn't read as `150`.
+    fn normalize<'a>(&self, amount: &'a str) -> Result<Cow<'a, str>, ParseAmountError> {
+        match self {
+            AmountFormat::DotDecimal => Ok(Cow::Borrowed(amount)),
+            AmountFormat::CommaDecimal => {
+                let (whole, decimals) = match amount.split_once(',') {
+                    Some((whole, decimals)) => (whole, Some(decimals)),
+                    None => (amount, None),
+                };
+                let unsigned = whole.strip_prefix(['-', '+']).unwrap_or(whole);
+                let mut groups = unsigned.split('.');
+                let first = groups.next().unwrap_or_default();
+                if unsigned.contains('.') && !(1..=3).contains(&first.len())
+                    || groups.any(|group| group.len() != 3)
+                    || decimals.is_some_and(|decimals| decimals.contains('.')) {
+                    return Err(ParseAmountError(amount.to_string()));
+                }
+                let whole = whole.replace('.', "");
+                Ok(Cow::Owned(match decimals {
+                    Some(decimals) => format!("{}.{}", whole, decimals),
+                    None => whole,
+                }))
+            }
+        }
+    }
+}
+
+/// The names of the columns of the transactions in the csv header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnMapping {
+    pub transaction_type: String,
+    pub client: String,
+    pub tx: String,
+    pub amount: String,
+}
+
+impl Default for ColumnMapping {
+    fn default() -> ColumnMapping {
+        ColumnMapping {
+            transaction_type: String::from("type"),
+            client: String::from("client"),
+            tx: String::from("tx"),
+            amount: String::from("amount"),
+        }
+    }
+}
+
+impl ColumnMapping {
+    /// Rename the columns of the header to the canonical names.
+    fn canonical_headers(&self, headers: &csv::ByteRecord) -> csv::ByteRecord {
+        headers.iter()
+            .map(|header| match header {
+                _ if header == self.transaction_type.as_bytes() => &b"type"[..],
+                _ if header == self.client.as_bytes() => &b"client"[..],
+                _ if header == self.tx.as_bytes() => &b"tx"[..],
+                _ if header == self.amount.as_bytes() => &b"amount"[..],
+                _ => header,
+            })
+            .collect()
+    }
+}
+
+/// A record that has been skipped because it couldn't be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedRecord {
+    pub line: u64, // 1-based line of the record
+    pub byte: u64, // Byte offset of the record
+    pub error: String,
+}
+
+/// Options of the csv reader.
+#[derive(Debug, Clone)]
+pub struct ReaderOptions {
+    pub error_policy: ErrorPolicy,
+    pub precision: u32, // Number of decimals to which the amounts are truncated
+    pub delimiter: u8, // Delimiter of the fields
+    pub amount_format: AmountFormat,
+    pub has_headers: bool, // Whether the first record is the header, otherwise the fields are in the default order
+    pub max_transactions: Option<u64>, // Number of transactions after which the reading stops
+    pub max_field_len: usize, // Maximum length of the fields in bytes, longer ones fail the reading
+    pub column_mapping: ColumnMapping, // Names of the columns in the header
+    pub comment_char: Option<u8>, // First character of the lines to skip
+    pub skip_unknown_types: bool, // Whether the records of unknown types are skipped, otherwise they can't be parsed
+    pub strict_amounts: bool, // Whether the disputes, resolves and chargebacks with an amount can't be parsed
+    pub empty_amount_policy: EmptyAmountPolicy, // How the deposits and withdrawals with an empty amount are handled
+    pub input_format: InputFormat, // Format of the transactions, the csv options only apply to csv
+    pub trim: Trim, // Parts of the csv whose fields are trimmed
+}
+
+impl Default for ReaderOptions {
+    fn default() -> ReaderOptions {
+        ReaderOptions {
+            error_policy: ErrorPolicy::default(),
+            precision: amount::DEFAULT_PRECISION,
+            delimiter: b',',
+            amount_format: AmountFormat::default(),
+            has_headers: true,
+            max_transactions: None,
+            max_field_len: DEFAULT_MAX_FIELD_LEN,
+            column_mapping: ColumnMapping::default(),
+            comment_char: None,
+            skip_unknown_types: false,
+            strict_amounts: false,
+            empty_amount_policy: EmptyAmountPolicy::default(),
+            input_format: InputFormat::default(),
+            trim: Trim::default(),
+        }
+    }
+}
 
 /// CSV reader for transaction files.
 ///
 /// `csv::Reader` uses a `BufReader` internally which will read large parts of the file at once
 /// and store them into a buffer until they are consumed. This should suffice for big files as we won't
 /// load the entire file at once.
+///
+/// The records are read as bytes, and only the fields of the transactions are required to be valid
+/// UTF-8, so that invalid bytes in other columns, e.g. trailing columns of legacy files, are tolerated.
 pub struct CsvReader<R: io::Read> {
-    csv_reader: csv::Reader<R>,
+    csv_reader: csv::Reader<FieldLimit<R>>,
+    headers: Option<csv::ByteRecord>,
+    options: ReaderOptions,
+    skipped: Vec<SkippedRecord>,
+    read: u64, // Number of transactions read
+    limit_reached: bool,
 }
 
 impl<R: io::Read> CsvReader<R> {
-    pub fn new(reader: R) -> CsvReader<R> {
+    pub fn new(reader: R, options: ReaderOptions) -> CsvReader<R> {
         CsvReader {
             csv_reader: csv::ReaderBuilder::new()
-                .has_headers(true) // Include headers
-                .delimiter(b',') // Delimited by commas
-                .trim(Trim::All) // Ignore all whitespaces
+                .has_headers(options.has_headers) // Include headers by default
+                .delimiter(options.delimiter) // Delimited by commas by default
+                .quoting(true) // Unquote the quoted fields, e.g. "1.50"
+                .trim(options.trim.to_csv()) // Ignore all whitespaces by default
                 .flexible(true) // Allow records of unequal length
-                .from_reader(reader)
+                .comment(options.comment_char) // No comments by default
+                .from_reader(FieldLimit::new(reader, &options)),
+            headers: None,
+            options,
+            skipped: Vec::new(),
+            read: 0,
+            limit_reached: false,
         }
     }
 
     /// Retrieve the next transaction in the csv.
-    pub fn next(&mut self) -> Result<Option<Transaction>, Box<dyn Error>> {
-        if let Some(record) = self.csv_reader.deserialize().next() {
-            let record: Record = record?; // Deserialization
-            return Ok(Some(record.to_transaction()?));
+    ///
+    /// With [`ErrorPolicy::SkipBad`], the records that can't be parsed are skipped and collected
+    /// in [`CsvReader::skipped`]. With `skip_unknown_types`, the records of unknown types are
+    /// skipped without being collected, as are the deposits and withdrawals with an empty amount with
+    /// [`EmptyAmountPolicy::Skip`]. The reading stops after `max_transactions` transactions, if any.
+    pub fn next(&mut self) -> Result<Option<Transaction>, EngineError> {
+        if self.headers.is_none() {
+            // Without headers, the fields are in the default order.
+            self.headers = Some(if self.options.has_headers {
+                let headers = self.csv_reader.byte_headers()
+                    .map(|headers| self.options.column_mapping.canonical_headers(headers));
+                headers.map_err(|err| self.read_error(err))?
+            } else {
+                csv::ByteRecord::from(vec!["type", "client", "tx", "amount"])
+            });
+        }
+
+        let mut byte_record = csv::ByteRecord::new();
+        if self.options.max_transactions.is_some_and(|max_transactions| self.read >= max_transactions) {
+            // The limit is only reached if there are records left.
+            self.limit_reached = self.limit_reached || self.read_byte_record(&mut byte_record)?;
+            return Ok(None);
+        }
+
+        while self.read_byte_record(&mut byte_record)? {
+            // The csv reader doesn't trim the first byte record without headers.
+            if matches!(self.options.trim, Trim::All | Trim::Fields) {
+                byte_record.trim();
+            }
+            match parse_record(&byte_record, self.headers.as_ref(), &self.options) {
+                Ok(Some(transaction)) => {
+                    self.read += 1;
+                    return Ok(Some(transaction));
+                }
+                Ok(None) => {
+                    log::debug!("Skipped record of unknown type or without amount at line {}", byte_record.position().map_or(0, csv::Position::line));
+                }
+                Err(err) => {
+                    let position = byte_record.position().cloned().unwrap_or_else(csv::Position::new);
+                    log::debug!("Unparsable record at line {}: {}", position.line(), err);
+                    match self.options.error_policy {
+                        ErrorPolicy::StrictParse => {
+                            // The records with oversized fields are too long for the error.
+                            let raw_record = (byte_record.as_slice().len() <= self.options.max_field_len)
+                                .then(|| byte_record.iter().map(String::from_utf8_lossy).collect::<Vec<_>>().join(","));
+                            return Err(EngineError::CsvParse {
+                                line: position.line(),
+                                record: raw_record,
+                                source: err,
+                            });
+                        }
+                        ErrorPolicy::SkipBad => {
+                            self.skipped.push(SkippedRecord {
+                                line: position.line(),
+                                byte: position.byte(),
+                                error: err.to_string(),
+                            });
+                        }
+                    }
+                }
+            }
         }
         Ok(None)
     }
+
+    /// The records that have been skipped so far.
+    pub fn skipped(&self) -> &[SkippedRecord] {
+        &self.skipped
+    }
+
+    /// Whether the reading stopped at `max_transactions` before the end of the csv.
+    pub fn limit_reached(&self) -> bool {
+        self.limit_reached
+    }
+
+    fn read_byte_record(&mut self, byte_record: &mut csv::ByteRecord) -> Result<bool, EngineError> {
+        let result = self.csv_reader.read_byte_record(byte_record);
+        result.map_err(|err| self.read_error(err))
+    }
+
+    /// The error of the csv reader, with the line of the oversized field if the input was cut short
+    /// by the [`FieldLimit`].
+    fn read_error(&self, err: csv::Error) -> EngineError {
+        match err.kind() {
+            csv::ErrorKind::Io(io_err) if io_err.get_ref().is_some_and(|source| source.is::<CsvReaderError>()) => {
+                EngineError::CsvParse {
+                    line: self.csv_reader.get_ref().line,
+                    record: None,
+                    source: Box::new(CsvReaderError(io_err.to_string())),
+                }
+            }
+            _ => err.into(),
+        }
+    }
+}
+
+/// Reader failing on the first field longer than `max_field_len` bytes, quotes included, before the
+/// csv reader buffers it, so that a single oversized field can't exhaust the memory.
+///
+/// The fields are delimited as the csv reader does: by the delimiter and the line endings outside
+/// quotes, a quote only opening a quoted field at its start, and a doubled quote being escaped. The
+/// comment lines aren't fields, as the csv reader skips them without buffering them.
+struct FieldLimit<R> {
+    reader: R,
+    max_field_len: usize,
+    delimiter: u8,
+    comment_char: Option<u8>,
+    field_len: usize, // Bytes of the current field read so far
+    quoted: bool, // Whether the current field is inside quotes
+    quote_closed: bool, // Whether the last byte closed the quotes, or is the first of an escaped quote
+    line_start: bool, // Whether the next byte starts a line
+    in_comment: bool, // Whether the current line is a comment
+    line: u64, // 1-based line of the current byte
+    exceeded: bool,
+}
+
+impl<R> FieldLimit<R> {
+    fn new(reader: R, options: &ReaderOptions) -> FieldLimit<R> {
+        FieldLimit {
+            reader,
+            max_field_len: options.max_field_len,
+            delimiter: options.delimiter,
+            comment_char: options.comment_char,
+            field_len: 0,
+            quoted: false,
+            quote_closed: false,
+            line_start: true,
+            in_comment: false,
+            line: 1,
+            exceeded: false,
+        }
+    }
+
+    /// Account for the next byte of the input, returning whether its field is too long.
+    fn push(&mut self, byte: u8) -> bool {
+        let line_start = std::mem::replace(&mut self.line_start, false);
+        if byte == b'\n' {
+            self.line += 1;
+        }
+        if self.in_comment || (line_start && self.comment_char == Some(byte)) {
+            self.in_comment = byte != b'\n';
+            self.line_start = !self.in_comment;
+            return false;
+        }
+        if self.quote_closed {
+            // A quote right after the closing one is escaped, and the field stays quoted.
+            self.quote_closed = false;
+            self.quoted = byte == b'"';
+        } else if byte == b'"' && (self.quoted || self.field_len == 0) {
+            self.quote_closed = self.quoted;
+            self.quoted = !self.quoted;
+        }
+        if !self.quoted && !self.quote_closed && (byte == self.delimiter || byte == b'\n' || byte == b'\r') {
+            self.field_len = 0;
+            self.line_start = byte == b'\n';
+            return false;
+        }
+        self.field_len += 1;
+        self.field_len > self.max_field_len
+    }
+
+    fn error(&self) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, CsvReaderError(format!("Field longer than the maximum of {} bytes", self.max_field_len)))
+    }
+}
+
+impl<R: io::Read> io::Read for FieldLimit<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.exceeded {
+            return Err(self.error());
+        }
+        let len = self.reader.read(buf)?;
+        for (i, &byte) in buf[..len].iter().enumerate() {
+            if self.push(byte) {
+                // The bytes before the oversized field are still handed out, the error comes next.
+                self.exceeded = true;
+                return if i > 0 { Ok(i) } else { Err(self.error()) };
+            }
+        }
+        Ok(len)
+    }
+}
+
+/// Parse a single line of csv, without header, into a transaction, e.g. `deposit,1,1,5.0`.
+pub(crate) fn parse_line(line: &str) -> Result<Transaction, EngineError> {
+    let mut csv_reader = CsvReader::new(line.as_bytes(), ReaderOptions { has_headers: false, ..ReaderOptions::default() });
+    let transaction = csv_reader.next()?;
+    let error = match transaction {
+        Some(_) if csv_reader.next()?.is_some() => "More than one record in the line",
+        Some(transaction) => return Ok(transaction),
+        None => "Missing record in the line",
+    };
+    Err(EngineError::CsvParse { line: 1, record: Some(line.to_string()), source: Box::new(CsvReaderError(error.to_string())) })
+}
+
+/// Deserialize and validate a single csv record, or skip it if its type is unknown and the options
+/// allow it.
+fn parse_record(
+    byte_record: &csv::ByteRecord,
+    headers: Option<&csv::ByteRecord>,
+    options: &ReaderOptions,
+) -> Result<Option<Transaction>, Box<dyn Error + Send + Sync>> {
+    // Only the fields of the record are decoded, so the other ones may not be valid UTF-8.
+    let record: Record = byte_record.deserialize(headers)?; // Deserialization
+    Ok(record.into_transaction(options)?)
 }
 
 #[derive(Debug)]
-struct CsvReaderError(String);
+pub(crate) struct CsvReaderError(String);
 
 impl std::error::Error for CsvReaderError {}
 
@@ -50,58 +440,114 @@ impl fmt::Display for CsvReaderError {
     }
 }
 
+/// A record of a transaction, in the csv or in the JSON lines.
 #[derive(Deserialize)]
-struct Record {
+pub(crate) struct Record {
     #[serde(rename = "type")]
-    transaction_type: RecordType,
-    client: u16,
+    transaction_type: RecordTypeField,
+    client: ClientId,
     tx: u32,
-    amount: Option<f64>,
+    amount: Option<String>,
 }
 
 impl Record {
-    pub fn to_transaction(self) -> Result<Transaction, Box<dyn Error>> {
-        let transaction = match self.transaction_type {
+    pub(crate) fn into_transaction(self, options: &ReaderOptions) -> Result<Option<Transaction>, CsvReaderError> {
+        let transaction_type = match self.transaction_type {
+            RecordTypeField::Known(transaction_type) => transaction_type,
+            RecordTypeField::Unknown(_) if options.skip_unknown_types => return Ok(None),
+            RecordTypeField::Unknown(name) => return Err(CsvReaderError(format!("Unknown transaction type {}", name))),
+        };
+        let amount = match (&transaction_type, self.amount) {
+            (RecordType::Deposit | RecordType::Withdrawal, None) => match options.empty_amount_policy {
+                EmptyAmountPolicy::Error => None,
+                EmptyAmountPolicy::TreatAsZero => Some(String::from("0")),
+                EmptyAmountPolicy::Skip => return Ok(None),
+            },
+            (_, amount) => amount,
+        };
+        let transaction = match transaction_type {
             RecordType::Deposit => {
                 Transaction::new(
-                    TransactionType::Deposit { amount: self.amount.ok_or_else(|| CsvReaderError(String::from("Missing amount for deposit")))? },
+                    TransactionType::Deposit { amount: parse_amount(amount, options, "deposit")? },
                     self.client,
                     self.tx,
                 )
             }
             RecordType::Withdrawal => {
                 Transaction::new(
-                    TransactionType::Withdrawal { amount: self.amount.ok_or_else(|| CsvReaderError(String::from("Missing amount for withdrawal")))? },
+                    TransactionType::Withdrawal { amount: parse_amount(amount, options, "withdrawal")? },
                     self.client,
                     self.tx,
                 )
             }
             RecordType::Dispute => {
                 Transaction::new(
-                    TransactionType::Dispute,
+                    TransactionType::Dispute { amount: parse_portion(amount, options, "dispute")? },
                     self.client,
                     self.tx,
                 )
             }
             RecordType::Resolve => {
                 Transaction::new(
-                    TransactionType::Resolve,
+                    TransactionType::Resolve { amount: parse_portion(amount, options, "resolve")? },
                     self.client,
                     self.tx,
                 )
             }
             RecordType::Chargeback => {
                 Transaction::new(
-                    TransactionType::Chargeback,
+                    TransactionType::Chargeback { amount: parse_portion(amount, options, "chargeback")? },
+                    self.client,
+                    self.tx,
+                )
+            }
+            RecordType::Freeze => Transaction::new(TransactionType::Freeze, self.client, self.tx),
+            RecordType::Thaw => Transaction::new(TransactionType::Thaw, self.client, self.tx),
+            RecordType::CreditLimit => {
+                Transaction::new(
+                    TransactionType::CreditLimit { amount: parse_amount(amount, options, "credit limit")? },
                     self.client,
                     self.tx,
                 )
             }
         };
-        Ok(transaction)
+        Ok(Some(transaction))
     }
 }
 
+/// Parse the amount of a transaction in the format and truncated to the precision of the options,
+/// and check that it is present and not negative.
+///
+/// Zero amounts are read, and then ignored by the transaction manager. The amounts are decimal
+/// numbers, so exponents such as `1e3` are invalid rather than read as floating point numbers.
+fn parse_amount(amount: Option<String>, options: &ReaderOptions, transaction_name: &str) -> Result<Amount, CsvReaderError> {
+    let amount = amount.ok_or_else(|| CsvReaderError(format!("Missing amount for {}", transaction_name)))?;
+    match options.amount_format.normalize(&amount).and_then(|normalized| amount::parse(&normalized, options.precision)) {
+        Ok(amount) if amount >= Amount::ZERO => Ok(amount),
+        _ => Err(CsvReaderError(format!("Invalid amount {} for {}", amount, transaction_name))),
+    }
+}
+
+/// Parse the optional amount of a dispute, resolve or chargeback, applying it to only a portion of
+/// the disputed transaction. With `strict_amounts`, the amount must be empty as in the spec, a
+/// populated one likely being a malformed record.
+fn parse_portion(amount: Option<String>, options: &ReaderOptions, transaction_name: &str) -> Result<Option<Amount>, CsvReaderError> {
+    if options.strict_amounts {
+        if let Some(amount) = amount {
+            return Err(CsvReaderError(format!("Unexpected amount {} for {}", amount, transaction_name)));
+        }
+    }
+    amount.map(|amount| parse_amount(Some(amount), options, transaction_name)).transpose()
+}
+
+/// The type of a record, which may be unknown, e.g. a type added upstream.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum RecordTypeField {
+    Known(RecordType),
+    Unknown(String),
+}
+
 #[derive(Debug, Deserialize)]
 enum RecordType {
     #[serde(rename = "deposit")]
@@ -114,4 +560,479 @@ enum RecordType {
     Resolve,
     #[serde(rename = "chargeback")]
     Chargeback,
-}
\ No newline at end of file
+    #[serde(rename = "credit_limit")]
+    CreditLimit,
+    #[serde(rename = "freeze")]
+    Freeze,
+    #[serde(rename = "thaw")]
+    Thaw,
+}
+
+impl RecordType {
+    /// All the record types, in the order in which they are documented.
+    pub fn all() -> &'static [RecordType] {
+        &[
+            RecordType::Deposit,
+            RecordType::Withdrawal,
+            RecordType::Dispute,
+            RecordType::Resolve,
+            RecordType::Chargeback,
+            RecordType::CreditLimit,
+            RecordType::Freeze,
+            RecordType::Thaw,
+        ]
+    }
+
+    /// The name of the record type in the `type` column.
+    pub fn name(&self) -> &'static str {
+        match self {
+            RecordType::Deposit => "deposit",
+            RecordType::Withdrawal => "withdrawal",
+            RecordType::Dispute => "dispute",
+            RecordType::Resolve => "resolve",
+            RecordType::Chargeback => "chargeback",
+            RecordType::CreditLimit => "credit_limit",
+            RecordType::Freeze => "freeze",
+            RecordType::Thaw => "thaw",
+        }
+    }
+
+    /// The fields of the record type, the optional ones in brackets.
+    pub fn fields(&self) -> &'static str {
+        match self {
+            RecordType::Deposit | RecordType::Withdrawal | RecordType::CreditLimit => "client, tx, amount",
+            RecordType::Dispute | RecordType::Resolve | RecordType::Chargeback => "client, tx, [amount]",
+            RecordType::Freeze | RecordType::Thaw => "client, tx",
+        }
+    }
+}
+
+/// The table of the supported record types and their fields.
+pub fn record_types() -> String {
+    let mut table = format!("{:<14}{}\n", "type", "fields");
+    for record_type in RecordType::all() {
+        table.push_str(&format!("{:<14}{}\n", record_type.name(), record_type.fields()));
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use super::*;
+
+    #[test]
+    fn read_transaction() {
+        let mut csv_reader = CsvReader::new("type,client,tx,amount\ndeposit,1,2,1.5\n".as_bytes(), ReaderOptions::default());
+        let transaction = csv_reader.next().unwrap().unwrap();
+        assert!(matches!(transaction.transaction_type(), TransactionType::Deposit { amount } if amount.to_string() == "1.5"));
+        assert_eq!(transaction.client(), 1);
+        assert_eq!(transaction.tx(), 2);
+        assert!(csv_reader.next().unwrap().is_none());
+    }
+
+    #[test]
+    fn read_reordered_columns() {
+        let input =
+            "client, type, tx, amount\n\
+            1, deposit, 2, 1.5\n\
+            1, dispute, 2\n";
+        let mut csv_reader = CsvReader::new(input.as_bytes(), ReaderOptions::default());
+        let transaction = csv_reader.next().unwrap().unwrap();
+        assert!(matches!(transaction.transaction_type(), TransactionType::Deposit { amount } if amount.to_string() == "1.5"));
+        assert_eq!(transaction.client(), 1);
+        assert_eq!(transaction.tx(), 2);
+        let transaction = csv_reader.next().unwrap().unwrap();
+        assert!(matches!(transaction.transaction_type(), TransactionType::Dispute { amount: None }));
+        assert!(csv_reader.next().unwrap().is_none());
+    }
+
+    #[test]
+    fn read_aliased_columns() {
+        let input =
+            "txn_type,customer,id,value\n\
+            deposit,1,2,1.5\n\
+            dispute,1,2,\n";
+        let column_mapping = ColumnMapping {
+            transaction_type: String::from("txn_type"),
+            client: String::from("customer"),
+            tx: String::from("id"),
+            amount: String::from("value"),
+        };
+        let mut csv_reader = CsvReader::new(input.as_bytes(), ReaderOptions { column_mapping, ..ReaderOptions::default() });
+        let transaction = csv_reader.next().unwrap().unwrap();
+        assert!(matches!(transaction.transaction_type(), TransactionType::Deposit { amount } if amount.to_string() == "1.5"));
+        assert_eq!(transaction.client(), 1);
+        assert_eq!(transaction.tx(), 2);
+        let transaction = csv_reader.next().unwrap().unwrap();
+        assert!(matches!(transaction.transaction_type(), TransactionType::Dispute { amount: None }));
+        assert!(csv_reader.next().unwrap().is_none());
+
+        // The canonical names are no longer recognized.
+        let mut csv_reader = CsvReader::new("type,customer,id,value\ndeposit,1,2,1.5\n".as_bytes(), ReaderOptions {
+            column_mapping: ColumnMapping { transaction_type: String::from("txn_type"), ..ColumnMapping::default() },
+            ..ReaderOptions::default()
+        });
+        assert!(csv_reader.next().is_err());
+    }
+
+    #[test]
+    fn read_all_record_types() {
+        for record_type in RecordType::all() {
+            let input = format!("type,client,tx,amount\n{},1,1,1.0\n", record_type.name());
+            let mut csv_reader = CsvReader::new(input.as_bytes(), ReaderOptions::default());
+            assert!(csv_reader.next().is_ok(), "{}", record_type.name());
+        }
+        let table = record_types();
+        assert_eq!(table.lines().count(), RecordType::all().len() + 1);
+        assert!(table.contains("dispute       client, tx, [amount]\n"), "{}", table);
+    }
+
+    #[test]
+    fn read_skipping_comments() {
+        let input =
+            "# Transactions of the day\n\
+            type,client,tx,amount\n\
+            deposit,1,1,1.5\n\
+            # Refunded by phone\n\
+            withdrawal,1,2,0.5\n";
+        let mut csv_reader = CsvReader::new(input.as_bytes(), ReaderOptions { comment_char: Some(b'#'), ..ReaderOptions::default() });
+        assert_eq!(csv_reader.next().unwrap().unwrap().tx(), 1);
+        assert_eq!(csv_reader.next().unwrap().unwrap().tx(), 2);
+        assert!(csv_reader.next().unwrap().is_none());
+
+        // Without a comment character, the comments are records that can't be parsed.
+        let options = ReaderOptions { error_policy: ErrorPolicy::SkipBad, has_headers: false, ..ReaderOptions::default() };
+        let mut csv_reader = CsvReader::new(input.as_bytes(), options);
+        while csv_reader.next().unwrap().is_some() {}
+        assert_eq!(csv_reader.skipped().len(), 3);
+    }
+
+    #[test]
+    fn read_padded_first_record_without_headers() {
+        let options = ReaderOptions { has_headers: false, ..ReaderOptions::default() };
+        let mut csv_reader = CsvReader::new(" deposit , 1 , 1 , 1.5 \n withdrawal , 1 , 2 , 0.5 \n".as_bytes(), options);
+        assert_eq!(csv_reader.next().unwrap().unwrap().amount(), Some("1.5".parse().unwrap()));
+        assert_eq!(csv_reader.next().unwrap().unwrap().amount(), Some("0.5".parse().unwrap()));
+        assert!(csv_reader.next().unwrap().is_none());
+    }
+
+    #[test]
+    fn read_invalid_utf8_in_other_columns() {
+        let input = b"type,client,tx,amount,note \xff\n\
+            deposit,1,1,1.5,caf\xe9\n\
+            withdrawal,1,2,0.5,\xff\xfe\n\
+            deposit,1,3,1\xff,\n";
+        let mut csv_reader = CsvReader::new(&input[..], ReaderOptions::default());
+        assert_eq!(csv_reader.next().unwrap().unwrap().amount(), Some("1.5".parse().unwrap()));
+        assert_eq!(csv_reader.next().unwrap().unwrap().amount(), Some("0.5".parse().unwrap()));
+        // The fields of the transactions must still be valid UTF-8.
+        let err = csv_reader.next().unwrap_err();
+        assert!(err.to_string().starts_with("Error at line 4: "), "{}", err);
+        assert!(err.to_string().ends_with("(record: deposit,1,3,1\u{fffd},)"), "{}", err);
+
+        let options = ReaderOptions { has_headers: false, ..ReaderOptions::default() };
+        let mut csv_reader = CsvReader::new(&b"deposit,2,4,2.0,\xc3\x28\n"[..], options);
+        assert_eq!(csv_reader.next().unwrap().unwrap().client(), 2);
+        assert!(csv_reader.next().unwrap().is_none());
+    }
+
+    #[test]
+    fn read_quoted_fields() {
+        let input = "type,client,tx,amount\ndeposit,1,1,\"1.50\"\n\"withdrawal\",\"1\",\"2\",\" 0.25 \"\ndispute,1,1,\"\"\n";
+        let mut csv_reader = CsvReader::new(input.as_bytes(), ReaderOptions::default());
+        let transaction = csv_reader.next().unwrap().unwrap();
+        assert!(matches!(transaction.transaction_type(), TransactionType::Deposit { amount } if amount.to_string() == "1.5"));
+        let transaction = csv_reader.next().unwrap().unwrap();
+        assert!(matches!(transaction.transaction_type(), TransactionType::Withdrawal { amount } if amount.to_string() == "0.25"));
+        assert_eq!((transaction.client(), transaction.tx()), (1, 2));
+        assert!(matches!(csv_reader.next().unwrap().unwrap().transaction_type(), TransactionType::Dispute { amount: None }));
+    }
+
+    #[test]
+    fn read_with_each_trim() {
+        let input = " type , client,tx,amount\n deposit ,1,1,1.0\n";
+        for trim in [Trim::All, Trim::Fields, Trim::Headers, Trim::None] {
+            let mut csv_reader = CsvReader::new(input.as_bytes(), ReaderOptions { trim, ..ReaderOptions::default() });
+            let result = csv_reader.next();
+            match trim {
+                Trim::All => assert!(result.unwrap().is_some()),
+                Trim::Fields | Trim::None => assert!(result.unwrap_err().to_string().contains("missing field `type`")),
+                Trim::Headers => assert!(result.unwrap_err().to_string().contains("Unknown transaction type  deposit ")),
+            }
+        }
+        // Without headers, the first record is trimmed as the other ones.
+        let input = " deposit ,1,1,1.0\n";
+        let options = ReaderOptions { has_headers: false, trim: Trim::Fields, ..ReaderOptions::default() };
+        assert!(CsvReader::new(input.as_bytes(), options.clone()).next().unwrap().is_some());
+        let options = ReaderOptions { trim: Trim::Headers, ..options };
+        assert!(CsvReader::new(input.as_bytes(), options).next().is_err());
+    }
+
+    #[test]
+    fn read_empty_amount_with_each_policy() {
+        let input = "type,client,tx,amount\ndeposit,1,1,\nwithdrawal,1,2\ndeposit,1,3,1.0\n";
+        let mut csv_reader = CsvReader::new(input.as_bytes(), ReaderOptions::default());
+        let err = csv_reader.next().unwrap_err();
+        assert_eq!(err.to_string(), "Error at line 2: Missing amount for deposit (record: deposit,1,1,)");
+
+        let options = ReaderOptions { empty_amount_policy: EmptyAmountPolicy::TreatAsZero, ..ReaderOptions::default() };
+        let mut csv_reader = CsvReader::new(input.as_bytes(), options);
+        assert_eq!(csv_reader.next().unwrap().unwrap().amount(), Some(Amount::ZERO));
+        assert_eq!(csv_reader.next().unwrap().unwrap().amount(), Some(Amount::ZERO));
+        assert_eq!(csv_reader.next().unwrap().unwrap().amount(), Some("1.0".parse().unwrap()));
+
+        let options = ReaderOptions { empty_amount_policy: EmptyAmountPolicy::Skip, ..ReaderOptions::default() };
+        let mut csv_reader = CsvReader::new(input.as_bytes(), options.clone());
+        assert_eq!(csv_reader.next().unwrap().unwrap().tx(), 3);
+        assert!(csv_reader.next().unwrap().is_none());
+        assert!(csv_reader.skipped().is_empty());
+
+        // The disputes, resolves and chargebacks are still read without amount.
+        let input = "type,client,tx,amount\ndispute,1,1,\n";
+        let mut csv_reader = CsvReader::new(input.as_bytes(), options);
+        assert!(matches!(csv_reader.next().unwrap().unwrap().transaction_type(), TransactionType::Dispute { amount: None }));
+    }
+
+    #[test]
+    fn read_unknown_type() {
+        let input = "type,client,tx,amount\nfoobar,1,1,5.0\ndeposit,1,2,1.0\n";
+        let mut csv_reader = CsvReader::new(input.as_bytes(), ReaderOptions::default());
+        let err = csv_reader.next().unwrap_err();
+        assert_eq!(err.to_string(), "Error at line 2: Unknown transaction type foobar (record: foobar,1,1,5.0)");
+
+        let mut csv_reader = CsvReader::new(input.as_bytes(), ReaderOptions { skip_unknown_types: true, ..ReaderOptions::default() });
+        assert_eq!(csv_reader.next().unwrap().unwrap().tx(), 2);
+        assert!(csv_reader.next().unwrap().is_none());
+        assert!(csv_reader.skipped().is_empty());
+    }
+
+    #[test]
+    fn read_amount_truncated_to_precision() {
+        let input = "type,client,tx,amount\ndeposit,1,1,1.123456789\n";
+        let mut csv_reader = CsvReader::new(input.as_bytes(), ReaderOptions { precision: 2, ..ReaderOptions::default() });
+        let transaction = csv_reader.next().unwrap().unwrap();
+        assert!(matches!(transaction.transaction_type(), TransactionType::Deposit { amount } if amount.to_string() == "1.12"));
+    }
+
+    #[test]
+    fn read_amount_below_precision_as_zero() {
+        let input = "type,client,tx,amount\ndeposit,1,1,0.001\n";
+        let mut csv_reader = CsvReader::new(input.as_bytes(), ReaderOptions { precision: 2, ..ReaderOptions::default() });
+        let transaction = csv_reader.next().unwrap().unwrap();
+        assert!(matches!(transaction.transaction_type(), TransactionType::Deposit { amount } if *amount == Amount::ZERO));
+    }
+
+    #[test]
+    fn read_partial_chargeback() {
+        let input = "type,client,tx,amount\ndispute,1,1,\nchargeback,1,1,0.5\nresolve,1,1,-0.5\n";
+        let mut csv_reader = CsvReader::new(input.as_bytes(), ReaderOptions::default());
+        let transaction = csv_reader.next().unwrap().unwrap();
+        assert!(matches!(transaction.transaction_type(), TransactionType::Dispute { amount: None }));
+        let transaction = csv_reader.next().unwrap().unwrap();
+        assert!(matches!(transaction.transaction_type(), TransactionType::Chargeback { amount: Some(amount) } if amount.to_string() == "0.5"));
+        let err = csv_reader.next().unwrap_err();
+        assert!(err.to_string().starts_with("Error at line 4: Invalid amount -0.5 for resolve"), "{}", err);
+    }
+
+    #[test]
+    fn read_strict_amounts() {
+        let input = "type,client,tx,amount\ndeposit,1,1,5.0\ndispute,1,1,5.0\ndispute,1,1,\n";
+        let options = ReaderOptions { strict_amounts: true, ..ReaderOptions::default() };
+        let mut csv_reader = CsvReader::new(input.as_bytes(), options.clone());
+        assert_eq!(csv_reader.next().unwrap().unwrap().tx(), 1);
+        let err = csv_reader.next().unwrap_err();
+        assert_eq!(err.to_string(), "Error at line 3: Unexpected amount 5.0 for dispute (record: dispute,1,1,5.0)");
+
+        let mut csv_reader = CsvReader::new(input.as_bytes(), ReaderOptions { error_policy: ErrorPolicy::SkipBad, ..options });
+        assert!(matches!(csv_reader.next().unwrap().unwrap().transaction_type(), TransactionType::Deposit { .. }));
+        assert!(matches!(csv_reader.next().unwrap().unwrap().transaction_type(), TransactionType::Dispute { amount: None }));
+        assert!(csv_reader.next().unwrap().is_none());
+        assert_eq!(csv_reader.skipped().len(), 1);
+        assert_eq!(csv_reader.skipped()[0].line, 3);
+    }
+
+    #[test]
+    fn read_comma_decimal_amounts() {
+        let input = "type;client;tx;amount\ndeposit;1;1;1.234,5\nwithdrawal;1;2;0,25\n";
+        let options = ReaderOptions { delimiter: b';', amount_format: AmountFormat::CommaDecimal, ..ReaderOptions::default() };
+        let mut csv_reader = CsvReader::new(input.as_bytes(), options.clone());
+        assert_eq!(csv_reader.next().unwrap().unwrap().amount(), Some("1234.5".parse().unwrap()));
+        assert_eq!(csv_reader.next().unwrap().unwrap().amount(), Some("0.25".parse().unwrap()));
+
+        // The periods only separate the thousands.
+        for amount in ["1.50", "0.5", "1.5,0", "12.34,5", "1.2345,0", ".123,4", "1,2.5"] {
+            let input = format!("type;client;tx;amount\ndeposit;1;1;{}\n", amount);
+            let mut csv_reader = CsvReader::new(input.as_bytes(), options.clone());
+            let err = csv_reader.next().unwrap_err();
+            assert!(err.to_string().starts_with(&format!("Error at line 2: Invalid amount {} for deposit", amount)), "{}", err);
+        }
+        assert_eq!(AmountFormat::CommaDecimal.normalize("1.234,50").unwrap(), "1234.50");
+        assert_eq!(AmountFormat::CommaDecimal.normalize("12.345.678").unwrap(), "12345678");
+        assert_eq!(AmountFormat::CommaDecimal.normalize("1.50"), Err(ParseAmountError(String::from("1.50"))));
+    }
+
+    #[test]
+    fn read_without_headers() {
+        let input = "deposit,1,1,1.5\ndispute,1,1\n";
+        let mut csv_reader = CsvReader::new(input.as_bytes(), ReaderOptions { has_headers: false, ..ReaderOptions::default() });
+        let transaction = csv_reader.next().unwrap().unwrap();
+        assert!(matches!(transaction.transaction_type(), TransactionType::Deposit { amount } if amount.to_string() == "1.5"));
+        assert_eq!(transaction.tx(), 1);
+        let transaction = csv_reader.next().unwrap().unwrap();
+        assert!(matches!(transaction.transaction_type(), TransactionType::Dispute { amount: None }));
+        assert!(csv_reader.next().unwrap().is_none());
+    }
+
+    #[test]
+    fn read_max_transactions() {
+        let options = ReaderOptions { max_transactions: Some(2), ..ReaderOptions::default() };
+        let mut csv_reader = CsvReader::new(include_str!("../sample.csv").as_bytes(), options);
+        assert_eq!(csv_reader.next().unwrap().unwrap().tx(), 1);
+        assert_eq!(csv_reader.next().unwrap().unwrap().tx(), 2);
+        assert!(!csv_reader.limit_reached());
+        assert!(csv_reader.next().unwrap().is_none());
+        assert!(csv_reader.limit_reached());
+
+        let options = ReaderOptions { max_transactions: Some(1), ..ReaderOptions::default() };
+        let mut csv_reader = CsvReader::new("type,client,tx,amount\ndeposit,1,1,1.0\n".as_bytes(), options);
+        assert!(csv_reader.next().unwrap().is_some());
+        assert!(csv_reader.next().unwrap().is_none());
+        assert!(!csv_reader.limit_reached());
+    }
+
+    #[test]
+    fn read_credit_limit() {
+        let mut csv_reader = CsvReader::new("type,client,tx,amount\ncredit_limit,1,1,5.0\ncredit_limit,1,2,\n".as_bytes(), ReaderOptions::default());
+        let transaction = csv_reader.next().unwrap().unwrap();
+        assert!(matches!(transaction.transaction_type(), TransactionType::CreditLimit { amount } if amount.to_string() == "5.0"));
+        let err = csv_reader.next().unwrap_err();
+        assert!(err.to_string().starts_with("Error at line 3: Missing amount for credit limit"), "{}", err);
+    }
+
+    #[test]
+    fn read_oversized_field_fails() {
+        let input = format!("type,client,tx,amount\ndeposit,1,1,1.{}\n", "0".repeat(64));
+        let options = ReaderOptions { max_field_len: 32, ..ReaderOptions::default() };
+        let mut csv_reader = CsvReader::new(input.as_bytes(), options.clone());
+        let err = csv_reader.next().unwrap_err();
+        assert_eq!(err.to_string(), "Error at line 2: Field longer than the maximum of 32 bytes");
+
+        // The rest of the input can't be read, even to skip the record.
+        let mut csv_reader = CsvReader::new(input.as_bytes(), ReaderOptions { error_policy: ErrorPolicy::SkipBad, ..options.clone() });
+        assert!(matches!(csv_reader.next(), Err(EngineError::CsvParse { line: 2, record: None, .. })));
+
+        // The quotes, the delimiters in quotes and the comments count as the csv reader reads them.
+        let input = "type,client,tx,amount\n# a comment longer than the fields\n\"deposit\",1,\"1\",\"1.\"\"0,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,\"\n";
+        let options = ReaderOptions { comment_char: Some(b'#'), ..options };
+        let err = CsvReader::new(input.as_bytes(), options.clone()).next().unwrap_err();
+        assert_eq!(err.to_string(), "Error at line 3: Field longer than the maximum of 32 bytes");
+        let input = "type,client,tx,amount\n# a comment longer than the fields\n\"deposit\",1,1,\"1.0\"\n";
+        assert!(CsvReader::new(input.as_bytes(), options).next().unwrap().is_some());
+    }
+
+    #[test]
+    fn read_oversized_field_stops_before_buffering_it() {
+        /// Endless amount field, counting the bytes read from it.
+        struct EndlessField<'a> {
+            read: &'a Cell<usize>,
+        }
+
+        impl io::Read for EndlessField<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let header = b"type,client,tx,amount\ndeposit,1,1,";
+                let len = if self.read.get() == 0 {
+                    buf[..header.len()].copy_from_slice(header);
+                    header.len()
+                } else {
+                    buf.fill(b'1');
+                    buf.len()
+                };
+                self.read.set(self.read.get() + len);
+                Ok(len)
+            }
+        }
+
+        let read = Cell::new(0);
+        let options = ReaderOptions { max_field_len: 1024, ..ReaderOptions::default() };
+        let mut csv_reader = CsvReader::new(EndlessField { read: &read }, options);
+        assert!(matches!(csv_reader.next(), Err(EngineError::CsvParse { line: 2, .. })));
+        assert!(read.get() < 64 * 1024, "{} bytes read", read.get());
+    }
+
+    #[test]
+    fn read_negative_amount_fails() {
+        let mut csv_reader = CsvReader::new("type,client,tx,amount\ndeposit,1,1,-5.0\n".as_bytes(), ReaderOptions::default());
+        let err = csv_reader.next().unwrap_err();
+        assert_eq!(err.to_string(), "Error at line 2: Invalid amount -5.0 for deposit (record: deposit,1,1,-5.0)");
+    }
+
+    #[test]
+    fn read_non_finite_amount_fails() {
+        for amount in ["NaN", "inf", "-inf"] {
+            let input = format!("type,client,tx,amount\nwithdrawal,1,1,{}\n", amount);
+            let mut csv_reader = CsvReader::new(input.as_bytes(), ReaderOptions::default());
+            let err = csv_reader.next().unwrap_err();
+            assert!(err.to_string().starts_with("Error at line 2: Invalid amount"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn read_exponent_amount_fails() {
+        let input = "type,client,tx,amount\ndeposit,1,1,1e3\nwithdrawal,1,2,1E-2\ndeposit,1,3,1.0\n";
+        let mut csv_reader = CsvReader::new(input.as_bytes(), ReaderOptions::default());
+        let err = csv_reader.next().unwrap_err();
+        assert_eq!(err.to_string(), "Error at line 2: Invalid amount 1e3 for deposit (record: deposit,1,1,1e3)");
+
+        let mut csv_reader = CsvReader::new(input.as_bytes(), ReaderOptions { error_policy: ErrorPolicy::SkipBad, ..ReaderOptions::default() });
+        assert_eq!(csv_reader.next().unwrap().unwrap().tx(), 3);
+        assert_eq!(csv_reader.skipped().len(), 2);
+        assert_eq!(csv_reader.skipped()[0].error, "Invalid amount 1e3 for deposit");
+        assert_eq!(csv_reader.skipped()[1].error, "Invalid amount 1E-2 for withdrawal");
+    }
+
+    #[test]
+    fn read_error_reports_line() {
+        let input =
+            "type,client,tx,amount\n\
+            deposit,1,1,1.0\n\
+            deposit,1,2,1.0\n\
+            withdrawal,1,3,\n";
+        let mut csv_reader = CsvReader::new(input.as_bytes(), ReaderOptions::default());
+        csv_reader.next().unwrap();
+        csv_reader.next().unwrap();
+        let err = csv_reader.next().unwrap_err();
+        assert_eq!(err.to_string(), "Error at line 4: Missing amount for withdrawal (record: withdrawal,1,3,)");
+    }
+
+    #[test]
+    fn read_malformed_record_fails() {
+        let mut csv_reader = CsvReader::new("type,client,tx,amount\ndeposit,a,1,1.0\n".as_bytes(), ReaderOptions::default());
+        match csv_reader.next() {
+            Err(EngineError::CsvParse { line, record, .. }) => {
+                assert_eq!(line, 2);
+                assert_eq!(record.unwrap(), "deposit,a,1,1.0");
+            }
+            result => panic!("Unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn skip_bad_records() {
+        let input =
+            "type,client,tx,amount\n\
+            deposit,1,1,1.0\n\
+            deposit,x,2,1.0\n\
+            withdrawal,1,3,\n\
+            deposit,1,4,2.0\n";
+        let mut csv_reader = CsvReader::new(input.as_bytes(), ReaderOptions { error_policy: ErrorPolicy::SkipBad, ..ReaderOptions::default() });
+        assert_eq!(csv_reader.next().unwrap().unwrap().tx(), 1);
+        assert_eq!(csv_reader.next().unwrap().unwrap().tx(), 4);
+        assert!(csv_reader.next().unwrap().is_none());
+        assert_eq!(csv_reader.skipped().len(), 2);
+        assert_eq!(csv_reader.skipped()[0].line, 3);
+        assert_eq!(csv_reader.skipped()[0].byte, 38);
+        assert_eq!(csv_reader.skipped()[1].line, 4);
+        assert_eq!(csv_reader.skipped()[1].error, "Missing amount for withdrawal");
+    }
+}
@@ -1,65 +1,184 @@
 //! # CSV Writer utilities for client accounts.
 
-use std::error::Error;
-use csv;
-use csv::{IntoInnerError, Writer};
+use std::io;
 use serde::{Serialize};
-use crate::transaction_manager::{ClientAccount};
+use crate::amount::{self, Amount, RoundingMode};
+use crate::error::EngineError;
+use crate::transaction_manager::{ClientAccount, ClientId};
+
+/// Options of the client account writers.
+#[derive(Debug, Clone)]
+pub struct WriterOptions {
+    pub precision: u32, // Number of decimals to which the amounts are rounded
+    pub rounding_mode: RoundingMode, // How the amounts are rounded to the precision
+    pub include_tx_count: bool, // Whether to write the number of transactions of the clients
+    pub include_locked_by: bool, // Whether to write the chargeback that locked the accounts
+    pub include_last_tx: bool, // Whether to write the last transaction applied to the accounts
+    pub include_totals: bool, // Whether to write a final row with the totals of the accounts
+    pub delimiter: u8, // Delimiter of the csv fields
+}
+
+impl Default for WriterOptions {
+    fn default() -> WriterOptions {
+        WriterOptions {
+            precision: amount::DEFAULT_PRECISION,
+            rounding_mode: RoundingMode::default(),
+            include_tx_count: false,
+            include_locked_by: false,
+            include_last_tx: false,
+            include_totals: false,
+            delimiter: b',',
+        }
+    }
+}
 
 /// CSV writer for client accounts.
+///
+/// The header is written with the first client account, or on its own if there is none. The
+/// totals row, if any, is written after the last one.
 pub struct CsvWriter<W: std::io::Write> {
     csv_writer: csv::Writer<W>,
+    count: usize,
+    totals: Totals,
+    options: WriterOptions,
 }
 
 impl<W: std::io::Write> CsvWriter<W> {
-    pub fn new(writer: W) -> CsvWriter<W> {
+    pub fn new(writer: W, options: WriterOptions) -> CsvWriter<W> {
         CsvWriter {
             csv_writer: csv::WriterBuilder::new()
                 .has_headers(true)
-                .delimiter(b',')
+                .delimiter(options.delimiter)
+                .terminator(csv::Terminator::Any(b'\n')) // Same line endings on all platforms
                 .double_quote(false)
                 .flexible(true)
-                .from_writer(writer)
+                .from_writer(writer),
+            count: 0,
+            totals: Totals::default(),
+            options,
         }
     }
 
     /// Write a single client account to the csv.
-    pub fn write(&mut self, client_account: &ClientAccount) -> Result<(), Box<dyn Error>> {
-        self.csv_writer.serialize(Record::new(client_account))?;
+    pub fn write(&mut self, client_account: &ClientAccount) -> Result<(), EngineError> {
+        self.csv_writer.serialize(Record::new(client_account, &self.options)?)?;
+        self.totals.add(client_account);
+        self.count += 1;
         Ok(())
     }
 
-    pub fn into_inner(
-        self,
-    ) -> Result<W, IntoInnerError<Writer<W>>> {
-        self.csv_writer.into_inner()
+    /// Write the header if there was no client account and the totals row if enabled, flush the
+    /// csv and retrieve the underlying writer.
+    pub fn into_inner(mut self) -> Result<W, EngineError> {
+        if self.count == 0 {
+            self.csv_writer.write_record(header(&self.options))?;
+        }
+        if self.options.include_totals {
+            self.csv_writer.write_record(self.totals.row(&self.options))?;
+        }
+        self.csv_writer.into_inner().map_err(|err| EngineError::Io(io::Error::new(err.error().kind(), err.error().to_string())))
     }
+
 }
 
+/// The names of the columns of the client accounts written with the options, as in the header of
+/// the csv.
+pub(crate) fn header(options: &WriterOptions) -> Vec<&'static str> {
+    let mut header = vec!["client", "available", "held", "total", "locked"];
+    if options.include_tx_count {
+        header.push("tx_count");
+    }
+    if options.include_locked_by {
+        header.push("locked_by");
+    }
+    if options.include_last_tx {
+        header.push("last_tx");
+    }
+    header
+}
+
+/// The totals of the written client accounts.
+///
+/// The sums are accumulated in units of `10^-MAX_PRECISION` on 128 bits, so that they can't
+/// overflow whatever the number of accounts, and only saturate at the bounds of [`Amount`] when
+/// written in the totals row, rather than wrapping around.
+#[derive(Debug, Default)]
+pub(crate) struct Totals {
+    available: i128,
+    held: i128,
+    locked: u64, // Number of locked accounts
+}
+
+impl Totals {
+    /// Add the balances of a client account to the totals.
+    pub(crate) fn add(&mut self, client_account: &ClientAccount) {
+        self.available += i128::from(client_account.available.units());
+        self.held += i128::from(client_account.held.units());
+        self.locked += u64::from(client_account.locked);
+    }
+
+    /// The totals row, e.g. `TOTAL,3.5000,1.0000,4.5000,1`, with the number of locked accounts in the
+    /// `locked` column and the extra columns left empty.
+    pub(crate) fn row(&self, options: &WriterOptions) -> Vec<String> {
+        let mut row = vec![String::from("TOTAL")];
+        for (column, units) in [("available", self.available), ("held", self.held), ("total", self.available + self.held)] {
+            row.push(format_amount(saturate(column, units), options));
+        }
+        row.push(self.locked.to_string());
+        row.resize(header(options).len(), String::new());
+        row
+    }
+}
+
+/// The amount of a number of units, saturated at the bounds of [`Amount`].
+fn saturate(column: &str, units: i128) -> Amount {
+    let saturated = units.clamp(-i128::from(i64::MAX), i128::from(i64::MAX));
+    if saturated != units {
+        log::warn!("The total {} funds overflow and are saturated", column);
+    }
+    Amount::from_units(saturated as i64)
+}
+
+/// The amount rounded and formatted with exactly the decimals of the options, as written in all
+/// the formats.
+pub(crate) fn format_amount(amount: Amount, options: &WriterOptions) -> String {
+    format!("{:.*}", options.precision as usize, amount.round(options.precision, options.rounding_mode))
+}
+
+/// A written client account, built from its [`AccountSummary`](crate::AccountSummary) for all the
+/// formats.
+///
+/// The amounts are serialized as strings to keep their exact decimal representation.
 #[derive(Serialize)]
-struct Record {
-    client: u16,
-    available: f64,
-    held: f64,
-    total: f64,
-    locked: bool
+pub(crate) struct Record {
+    pub client: ClientId,
+    pub available: String,
+    pub held: String,
+    pub total: String,
+    pub locked: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tx_count: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub locked_by: Option<Option<u32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_tx: Option<u32>,
 }
 
 impl Record {
     pub fn new(
-        client_account: &ClientAccount
-    ) -> Record {
-        Record {
-            client: client_account.client,
-            available: limit_to_4_decimals(client_account.available),
-            held: limit_to_4_decimals(client_account.held),
-            total: limit_to_4_decimals(client_account.available + client_account.held),
-            locked: client_account.locked
-        }
+        client_account: &ClientAccount,
+        options: &WriterOptions,
+    ) -> Result<Record, EngineError> {
+        let summary = client_account.summary().ok_or(EngineError::Overflow { client: client_account.client })?;
+        Ok(Record {
+            client: summary.client,
+            available: format_amount(summary.available, options),
+            held: format_amount(summary.held, options),
+            total: format_amount(summary.total, options),
+            locked: summary.locked,
+            tx_count: options.include_tx_count.then_some(client_account.tx_count),
+            locked_by: options.include_locked_by.then_some(client_account.locked_by),
+            last_tx: options.include_last_tx.then_some(client_account.last_tx),
+        })
     }
 }
-
-/// Limit the given float 64 to 4 decimals.
-fn limit_to_4_decimals(val: f64) -> f64{
-    f64::trunc(val  * 10000.0) / 10000.0
-}
\ No newline at end of file
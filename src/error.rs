@@ -0,0 +1,99 @@
+//! # Errors of the payments engine.
+
+use std::error::Error;
+use std::{fmt, io};
+use crate::ConsistencyError;
+use crate::transaction_manager::ClientId;
+
+/// The errors that can be returned by the payments engine.
+#[derive(Debug)]
+pub enum EngineError {
+    /// The configuration is invalid.
+    Config(String),
+    /// A csv record, or a JSON line, couldn't be parsed.
+    CsvParse {
+        line: u64, // 1-based line of the record, 0 if unknown
+        record: Option<String>, // Raw content of the record
+        source: Box<dyn Error + Send + Sync>,
+    },
+    /// Reading the input or writing the output failed.
+    Io(io::Error),
+    /// The client account is locked.
+    LockedAccount {
+        client: ClientId,
+    },
+    /// The total funds of the client account can't be represented.
+    Overflow {
+        client: ClientId,
+    },
+    /// The transaction id is used by several deposits or withdrawals.
+    DuplicateTransaction {
+        tx: u32,
+    },
+    /// The client accounts failed the self check.
+    Inconsistent(Vec<ConsistencyError>),
+    /// The withdrawal exceeds the available funds of the client account.
+    Overdraw {
+        client: ClientId,
+        tx: u32,
+    },
+}
+
+impl Error for EngineError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            EngineError::CsvParse { source, .. } => Some(source.as_ref()),
+            EngineError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EngineError::Config(message) => write!(f, "{}", message),
+            EngineError::CsvParse { line, record: Some(record), source } => {
+                write!(f, "Error at line {}: {} (record: {})", line, source, record)
+            }
+            EngineError::CsvParse { line, record: None, source } => write!(f, "Error at line {}: {}", line, source),
+            EngineError::Io(err) => write!(f, "{}", err),
+            EngineError::LockedAccount { client } => write!(f, "The client account {} is locked", client),
+            EngineError::Overflow { client } => write!(f, "The total funds of the client account {} overflow", client),
+            EngineError::DuplicateTransaction { tx } => write!(f, "The transaction id {} is used by several transactions", tx),
+            EngineError::Inconsistent(errors) => {
+                let errors: Vec<String> = errors.iter().map(ConsistencyError::to_string).collect();
+                write!(f, "The client accounts are inconsistent: {}", errors.join(", "))
+            }
+            EngineError::Overdraw { client, tx } => write!(f, "The withdrawal {} exceeds the available funds of the client account {}", tx, client),
+        }
+    }
+}
+
+impl From<io::Error> for EngineError {
+    fn from(err: io::Error) -> EngineError {
+        EngineError::Io(err)
+    }
+}
+
+impl From<csv::Error> for EngineError {
+    fn from(err: csv::Error) -> EngineError {
+        if let csv::ErrorKind::Io(_) = err.kind() {
+            if let csv::ErrorKind::Io(err) = err.into_kind() {
+                return EngineError::Io(err);
+            }
+            unreachable!("The kind of the error was checked");
+        }
+        EngineError::CsvParse {
+            line: err.position().map_or(0, |position| position.line()),
+            record: None,
+            source: Box::new(err),
+        }
+    }
+}
+
+impl From<serde_json::Error> for EngineError {
+    fn from(err: serde_json::Error) -> EngineError {
+        EngineError::Io(err.into())
+    }
+}
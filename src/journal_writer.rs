@@ -0,0 +1,87 @@
+//! # JSON Lines writer of the journal of the applied transactions.
+
+use std::io::Write;
+use serde::{Serialize};
+use crate::amount::Amount;
+use crate::error::EngineError;
+use crate::transaction_manager::{ClientId, Transaction, TransactionManager, TransactionType};
+
+/// The balances of a client account, zero before its first transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Balances {
+    pub available: Amount,
+    pub held: Amount,
+}
+
+impl Balances {
+    /// The balances of the given client in the transaction manager.
+    pub fn of(transaction_manager: &TransactionManager, client: ClientId) -> Balances {
+        Balances {
+            available: transaction_manager.available(client).unwrap_or(Amount::ZERO),
+            held: transaction_manager.held(client).unwrap_or(Amount::ZERO),
+        }
+    }
+}
+
+/// Journal writer of the applied transactions.
+///
+/// Each applied transaction is written as a JSON object on its own line, with the balances of the
+/// client account before and after it.
+pub struct JournalWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> JournalWriter<W> {
+    pub fn new(writer: W) -> JournalWriter<W> {
+        JournalWriter { writer }
+    }
+
+    /// Write a single applied transaction to the journal.
+    pub fn write(&mut self, transaction: &Transaction, before: Balances, after: Balances) -> Result<(), EngineError> {
+        let record = Record {
+            tx: transaction.tx(),
+            client: transaction.client(),
+            transaction_type: type_name(transaction.transaction_type()),
+            available_before: before.available,
+            available_after: after.available,
+            held_before: before.held,
+            held_after: after.held,
+        };
+        serde_json::to_writer(&mut self.writer, &record)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    /// Flush the journal and retrieve the underlying writer.
+    pub fn into_inner(mut self) -> Result<W, EngineError> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// The amounts are serialized as strings to keep their exact decimal representation.
+#[derive(Serialize)]
+struct Record {
+    tx: u32,
+    client: ClientId,
+    #[serde(rename = "type")]
+    transaction_type: &'static str,
+    available_before: Amount,
+    available_after: Amount,
+    held_before: Amount,
+    held_after: Amount,
+}
+
+/// The name of the transaction type, as in the csv of the transactions.
+fn type_name(transaction_type: &TransactionType) -> &'static str {
+    match transaction_type {
+        TransactionType::Deposit { .. } => "deposit",
+        TransactionType::Withdrawal { .. } => "withdrawal",
+        TransactionType::Dispute { .. } => "dispute",
+        TransactionType::Resolve { .. } => "resolve",
+        TransactionType::Chargeback { .. } => "chargeback",
+        TransactionType::CreditLimit { .. } => "credit_limit",
+        TransactionType::Freeze => "freeze",
+        TransactionType::Thaw => "thaw",
+    }
+}
@@ -0,0 +1,40 @@
+//! # JSON Writer utilities for client accounts.
+
+use std::io::Write;
+use crate::csv_writer::{Record, WriterOptions};
+use crate::error::EngineError;
+use crate::transaction_manager::ClientAccount;
+
+/// JSON writer for client accounts.
+///
+/// The client accounts are written one by one as elements of a single JSON array.
+pub struct JsonWriter<W: Write> {
+    writer: W,
+    count: usize,
+    options: WriterOptions,
+}
+
+impl<W: Write> JsonWriter<W> {
+    pub fn new(writer: W, options: WriterOptions) -> JsonWriter<W> {
+        JsonWriter {
+            writer,
+            count: 0,
+            options,
+        }
+    }
+
+    /// Write a single client account to the JSON array.
+    pub fn write(&mut self, client_account: &ClientAccount) -> Result<(), EngineError> {
+        self.writer.write_all(if self.count == 0 { b"[" } else { b"," })?;
+        serde_json::to_writer(&mut self.writer, &Record::new(client_account, &self.options)?)?;
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Close the JSON array, flush it and retrieve the underlying writer.
+    pub fn into_inner(mut self) -> Result<W, EngineError> {
+        self.writer.write_all(if self.count == 0 { b"[]" } else { b"]" })?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
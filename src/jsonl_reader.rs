@@ -0,0 +1,164 @@
+//! # JSON Lines Reader utilities for transactions.
+
+use std::error::Error;
+use std::io::{self, BufRead};
+use crate::csv_reader::{ErrorPolicy, ReaderOptions, Record, SkippedRecord};
+use crate::error::EngineError;
+use crate::Transaction;
+
+/// JSON Lines reader for transaction files.
+///
+/// Each line is a JSON object with the fields of a csv record, e.g.
+/// `{"type":"deposit","client":1,"tx":1,"amount":"1.0"}`. The amounts are strings, so that they
+/// keep their exact decimal representation, and are optional as in the csv. The empty lines are
+/// skipped, and the csv options, e.g. the delimiter or the header, don't apply.
+pub struct JsonlReader<R: io::Read> {
+    reader: io::BufReader<R>,
+    options: ReaderOptions,
+    skipped: Vec<SkippedRecord>,
+    line: u64, // Number of lines read
+    byte: u64, // Byte offset of the next line
+    read: u64, // Number of transactions read
+    limit_reached: bool,
+}
+
+impl<R: io::Read> JsonlReader<R> {
+    pub fn new(reader: R, options: ReaderOptions) -> JsonlReader<R> {
+        JsonlReader {
+            reader: io::BufReader::new(reader),
+            options,
+            skipped: Vec::new(),
+            line: 0,
+            byte: 0,
+            read: 0,
+            limit_reached: false,
+        }
+    }
+
+    /// Retrieve the next transaction in the JSON lines, handling the lines that can't be parsed as
+    /// [`crate::csv_reader::CsvReader::next`] does with the records.
+    pub fn next(&mut self) -> Result<Option<Transaction>, EngineError> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            let byte = self.byte;
+            let len = self.reader.read_line(&mut line)?;
+            if len == 0 {
+                return Ok(None);
+            }
+            self.line += 1;
+            self.byte += len as u64;
+            let record = line.trim();
+            if record.is_empty() {
+                continue;
+            }
+            if self.options.max_transactions.is_some_and(|max_transactions| self.read >= max_transactions) {
+                self.limit_reached = true;
+                return Ok(None);
+            }
+
+            match parse_line(record, &self.options) {
+                Ok(Some(transaction)) => {
+                    self.read += 1;
+                    return Ok(Some(transaction));
+                }
+                Ok(None) => {
+                    log::debug!("Skipped record of unknown type or without amount at line {}", self.line);
+                }
+                Err(err) => {
+                    log::debug!("Unparsable record at line {}: {}", self.line, err);
+                    match self.options.error_policy {
+                        ErrorPolicy::StrictParse => {
+                            return Err(EngineError::CsvParse {
+                                line: self.line,
+                                record: Some(record.to_string()),
+                                source: err,
+                            });
+                        }
+                        ErrorPolicy::SkipBad => {
+                            self.skipped.push(SkippedRecord {
+                                line: self.line,
+                                byte,
+                                error: err.to_string(),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// The lines that have been skipped so far.
+    pub fn skipped(&self) -> &[SkippedRecord] {
+        &self.skipped
+    }
+
+    /// Whether the reading stopped at `max_transactions` before the end of the JSON lines.
+    pub fn limit_reached(&self) -> bool {
+        self.limit_reached
+    }
+}
+
+/// Deserialize and validate a single JSON line, with the same rules as a csv record.
+fn parse_line(line: &str, options: &ReaderOptions) -> Result<Option<Transaction>, Box<dyn Error + Send + Sync>> {
+    let record: Record = serde_json::from_str(line)?;
+    Ok(record.into_transaction(options)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::Amount;
+    use crate::transaction_manager::TransactionType;
+
+    #[test]
+    fn read_transactions() {
+        let input = "{\"type\":\"deposit\",\"client\":1,\"tx\":1,\"amount\":\"1.5\"}\n\
+            \n\
+            {\"type\": \"dispute\", \"client\": 1, \"tx\": 1}\n\
+            {\"type\":\"resolve\",\"client\":1,\"tx\":1,\"amount\":null}\n";
+        let mut jsonl_reader = JsonlReader::new(input.as_bytes(), ReaderOptions::default());
+        let transaction = jsonl_reader.next().unwrap().unwrap();
+        assert!(matches!(transaction.transaction_type(), TransactionType::Deposit { amount } if amount.to_string() == "1.5"));
+        assert_eq!((transaction.client(), transaction.tx()), (1, 1));
+        assert!(matches!(jsonl_reader.next().unwrap().unwrap().transaction_type(), TransactionType::Dispute { amount: None }));
+        assert!(matches!(jsonl_reader.next().unwrap().unwrap().transaction_type(), TransactionType::Resolve { amount: None }));
+        assert!(jsonl_reader.next().unwrap().is_none());
+    }
+
+    #[test]
+    fn read_invalid_lines() {
+        let input = "{\"type\":\"deposit\",\"client\":1,\"tx\":1}\n\
+            {\"type\":\"deposit\",\"client\":1,\"tx\":2,\"amount\":1.5}\n\
+            deposit,1,3,1.0\n\
+            {\"type\":\"deposit\",\"client\":1,\"tx\":4,\"amount\":\"0.5\"}\n";
+        let mut jsonl_reader = JsonlReader::new(input.as_bytes(), ReaderOptions::default());
+        let err = jsonl_reader.next().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Error at line 1: Missing amount for deposit (record: {\"type\":\"deposit\",\"client\":1,\"tx\":1})",
+        );
+
+        let mut jsonl_reader = JsonlReader::new(input.as_bytes(), ReaderOptions { error_policy: ErrorPolicy::SkipBad, ..ReaderOptions::default() });
+        assert_eq!(jsonl_reader.next().unwrap().unwrap().amount(), Some("0.5".parse::<Amount>().unwrap()));
+        assert!(jsonl_reader.next().unwrap().is_none());
+        let skipped: Vec<(u64, u64)> = jsonl_reader.skipped().iter().map(|skipped| (skipped.line, skipped.byte)).collect();
+        assert_eq!(skipped, [(1, 0), (2, 37), (3, 87)]);
+    }
+
+    #[test]
+    fn read_max_transactions() {
+        let input = "{\"type\":\"deposit\",\"client\":1,\"tx\":1,\"amount\":\"1.0\"}\n\n";
+        let options = ReaderOptions { max_transactions: Some(1), ..ReaderOptions::default() };
+        let mut jsonl_reader = JsonlReader::new(input.as_bytes(), options.clone());
+        assert!(jsonl_reader.next().unwrap().is_some());
+        assert!(jsonl_reader.next().unwrap().is_none());
+        assert!(!jsonl_reader.limit_reached());
+
+        let input = format!("{}{}", input, "{\"type\":\"deposit\",\"client\":1,\"tx\":2,\"amount\":\"1.0\"}\n");
+        let mut jsonl_reader = JsonlReader::new(input.as_bytes(), options);
+        assert!(jsonl_reader.next().unwrap().is_some());
+        assert!(jsonl_reader.next().unwrap().is_none());
+        assert!(jsonl_reader.limit_reached());
+    }
+}
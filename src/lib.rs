@@ -9,89 +9,1247 @@
 //! * **deposit** - Deposit a certain amount into the client account.
 //! * **withdrawal** - Withdraw a certain amount from the client account.
 //! * **dispute** - Dispute the transaction with the given transaction id. Disputed funds are held
-//! until they are released. You can only dispute a deposit with a valid transaction id otherwise
-//! the dispute will be ignored. Additionally, you can only dispute a transaction once.
+//!   until they are released. You can only dispute a deposit or a withdrawal with a valid transaction
+//!   id otherwise the dispute will be ignored. Additionally, you can't dispute a transaction that is
+//!   already disputed or charged back, but you can dispute a resolved transaction again.
 //! * **resolve** - Resolves a disputed transaction with a given transaction id.
 //! * **chargeback** - Charges back the amount of a given transaction id from the client's balance.
+//!   The client account is then locked and any further transaction on it is ignored.
+//! * **credit_limit** - Set the amount by which the client account can be overdrawn by withdrawals.
+//! * **freeze** - Freeze the client account administratively: its deposits and withdrawals are
+//!   ignored until it is thawed.
+//! * **thaw** - Thaw the frozen client account.
 //!
 //! ## Example
 //!
 //! ```
-//! use std::{io, process};
-//! use payments_engine_rs::{Config, run};
+//! use std::process;
+//! use payments_engine_rs::{ConfigBuilder, ErrorPolicy, OutputFormat, run};
 //! let reader = "type,client,tx,amount\ndeposit,1,1,1.0".as_bytes();
-//! let writer = io::stdout();
-//! if let Err(e) = run(Config{reader, writer}) {
+//! let config = ConfigBuilder::new()
+//!     .reader(reader)
+//!     .output_format(OutputFormat::Csv)
+//!     .error_policy(ErrorPolicy::StrictParse)
+//!     .build();
+//! if let Err(e) = run(config) {
 //!     eprintln!("Application error: {}", e);
 //!     process::exit(1);
 //! }
 //! ```
 //!
 
+mod amount;
+#[cfg(feature = "checksum")]
+mod checksum_writer;
 mod csv_reader;
+mod jsonl_reader;
+mod error;
 mod transaction_manager;
 mod csv_writer;
+mod json_writer;
+mod table_writer;
+mod journal_writer;
+mod output_reader;
+mod step_runner;
+mod transaction_store;
+#[cfg(feature = "redb-store")]
+mod redb_store;
 
-use std::error::Error;
-use std::{env, fmt, io};
+use std::{fmt, io, panic, slice, thread};
+use std::io::{BufRead, Read};
+use std::borrow::Borrow;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
-use crate::transaction_manager::{Transaction, TransactionManager};
+use std::sync::mpsc;
+
+pub use crate::amount::{Amount, ParseAmountError, RoundingMode, DEFAULT_PRECISION, MAX_PRECISION};
+pub use crate::csv_reader::{record_types, AmountFormat, ColumnMapping, EmptyAmountPolicy, ErrorPolicy, SkippedRecord, Trim, DEFAULT_MAX_FIELD_LEN};
+use crate::csv_reader::ReaderOptions;
+use crate::csv_writer::WriterOptions;
+use crate::journal_writer::{Balances, JournalWriter};
+pub use crate::error::EngineError;
+pub use crate::step_runner::StepRunner;
+pub use crate::transaction_store::TransactionStore;
+#[cfg(feature = "redb-store")]
+pub use crate::redb_store::RedbStore;
+pub use crate::transaction_manager::{
+    AccountSummary, BatchReport, ClientAccount, ClientId, DisputeStats, HoldHook, IgnoreReason, IgnoredTransaction, OverdrawPolicy, ProcessedOutcome, Transaction, TransactionManager, TransactionState, TransactionType,
+};
 
 /// Stores the config required to run the payments engine.
 ///
-/// The config includes a reader that can be used to read the csv file.
+/// The config includes a reader that can be used to read the csv file, a writer for the client
+/// accounts and the options of the run.
 pub struct Config<R: io::Read, W: io::Write> {
+    /// The transactions, csv by default.
     pub reader: R,
+    /// The written client accounts.
     pub writer: W,
+    /// The options of the run.
+    pub options: Options,
 }
 
-impl<R: io::Read, W: io::Write> Config<R, W> {
-    pub fn new(mut args: env::Args) -> Result<Config<File, io::Stdout>, Box<dyn Error>> {
+/// The options of the run of the payments engine, apart from its reader and writer.
+///
+/// The amounts are truncated to `precision` decimals, at most [`MAX_PRECISION`], when they are
+/// read and written, unless another `rounding_mode` is set.
+pub struct Options {
+    /// The format in which the client accounts are written.
+    pub output_format: OutputFormat,
+    /// The policy for the records that can't be parsed.
+    pub error_policy: ErrorPolicy,
+    /// The number of decimals of the amounts.
+    pub precision: u32,
+    /// Whether a summary of the run is printed on stderr.
+    pub verbose: bool,
+    /// The order in which the client accounts are written.
+    pub output_order: OutputOrder,
+    /// Whether the client accounts are written with an extra `tx_count` column.
+    pub include_tx_count: bool,
+    /// Whether the client accounts are written with an extra `locked_by` column, the transaction id
+    /// of the chargeback that locked the account.
+    pub include_locked_by: bool,
+    /// Whether the client accounts are written with an extra `last_tx` column, the transaction id
+    /// of the last transaction applied to the account.
+    pub include_last_tx: bool,
+    /// Whether a final `TOTAL` row gives the sums of the balances of the written accounts,
+    /// saturated at the bounds of the amounts, and the number of locked ones, in the csv and table
+    /// formats only.
+    pub include_totals: bool,
+    /// The clients whose accounts are written, if any. The transactions of all the clients are
+    /// processed.
+    pub client_filter: Option<HashSet<ClientId>>,
+    /// The delimiter of the csv files, read and written, e.g. `b'\t'` for tab separated values.
+    pub delimiter: u8,
+    /// The clients whose transactions are ignored.
+    pub reserved_client_ids: HashSet<ClientId>,
+    /// How the amounts are rounded to `precision` decimals. Unless they are truncated, they are
+    /// read with all their decimals and only rounded when written.
+    pub rounding_mode: RoundingMode,
+    /// The format of the read amounts. With [`AmountFormat::CommaDecimal`], the csv files are
+    /// delimited by semicolons instead of commas.
+    pub amount_format: AmountFormat,
+    /// The callback invoked after each processed transaction with its outcome, e.g. to update
+    /// metrics.
+    pub on_transaction: Option<TransactionCallback>,
+    /// Whether the csv file of the transactions starts with a header, otherwise its fields are in
+    /// the order `type,client,tx,amount`.
+    pub has_headers: bool,
+    /// The number of transactions after which the reading stops, if any. The client accounts are
+    /// then written as they are, with a warning on stderr.
+    pub max_transactions: Option<u64>,
+    /// The maximum length of the fields of the csv file in bytes. The reading fails at the first
+    /// longer field, before buffering it, even with [`ErrorPolicy::SkipBad`].
+    pub max_field_len: usize,
+    /// The names of the columns in the header of the csv file, when they differ from
+    /// `type,client,tx,amount`.
+    pub column_mapping: ColumnMapping,
+    /// Whether the deposits and withdrawals are kept in memory to be disputed, otherwise all the
+    /// disputes, resolves and chargebacks are ignored.
+    pub disputes_expected: bool,
+    /// The journal to which each applied transaction is written, if any, as a JSON line with the
+    /// balances of the client account before and after it, e.g.
+    /// `{"tx":1,"client":1,"type":"deposit","available_before":"0.0","available_after":"1.0",...}`.
+    pub journal_writer: Option<Box<dyn io::Write>>,
+    /// The hook adjusting the amounts released by the resolves, see
+    /// [`TransactionManager::with_on_hold`].
+    pub on_hold: Option<HoldHook>,
+    /// The first character of the lines of the csv file to skip, if any, e.g. `b'#'`.
+    pub comment_char: Option<u8>,
+    /// Whether the records of unknown types are skipped, e.g. types added upstream, otherwise they
+    /// can't be parsed.
+    pub skip_unknown_types: bool,
+    /// The byte offset at which the reading is resumed, if any, with a seekable reader and
+    /// [`run_seekable`].
+    pub resume_at: Option<u64>,
+    /// Whether the disputes, resolves and chargebacks with an amount can't be parsed, instead of
+    /// applying to a portion of the disputed transaction.
+    pub strict_amounts: bool,
+    /// Whether the client accounts are read back from the output and checked as with
+    /// [`self_check`] before being written. The run fails without writing them if they are
+    /// inconsistent.
+    pub self_check: bool,
+    /// The number of transactions after which the deposits and withdrawals can't be disputed
+    /// anymore, if any, see [`TransactionManager::with_dispute_window`].
+    pub dispute_window: Option<u64>,
+    /// How the deposits and withdrawals with an empty amount are handled.
+    pub empty_amount_policy: EmptyAmountPolicy,
+    /// The format of the transactions, csv by default or JSON lines.
+    pub input_format: InputFormat,
+    /// The maximum number of client accounts, if any. The transactions that would create more are
+    /// ignored, see [`TransactionManager::with_max_clients`].
+    pub max_clients: Option<usize>,
+    /// How the withdrawals exceeding the available funds are handled.
+    pub overdraw_policy: OverdrawPolicy,
+    /// The parts of the csv file of the transactions whose fields are trimmed of their whitespaces,
+    /// both the header and the records by default.
+    pub trim: Trim,
+}
+
+/// A callback invoked with each processed transaction and its outcome.
+pub type TransactionCallback = Box<dyn FnMut(&Transaction, &ProcessedOutcome)>;
+
+/// The formats in which the client accounts can be written, with exactly `precision` decimals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// One csv row per client account, with a header.
+    #[default]
+    Csv,
+    /// A JSON array of client accounts, with the amounts as strings.
+    Json,
+    /// A table with borders and aligned columns, for human inspection.
+    Table,
+}
+
+/// The formats in which the transactions can be read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InputFormat {
+    /// One csv record per transaction, see the crate documentation.
+    #[default]
+    Csv,
+    /// One JSON object per line, e.g. `{"type":"deposit","client":1,"tx":1,"amount":"1.0"}`, with
+    /// the amounts as strings.
+    Jsonl,
+}
+
+/// The orders in which the client accounts can be written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputOrder {
+    /// Sorted by ascending client id.
+    #[default]
+    ClientIdAscending,
+    /// In the order in which the clients first appeared in the transactions.
+    FirstSeen,
+}
+
+impl Config<Box<dyn io::Read>, Box<dyn io::Write>> {
+    /// Create the config from the command line arguments.
+    ///
+    /// The transactions are read from the file given as argument, or from stdin if there is none.
+    /// With the `gzip` feature, files with a `.gz` extension are decompressed. The output can be
+    /// restricted to some clients with `--clients`, followed by comma separated client ids. The
+    /// client accounts are written to the file given with `-o` or `--output`, or to stdout. With
+    /// `--round-trip`, the written client accounts are read back and checked as with [`self_check`].
+    /// With `--stats`, the summary of the run, with the counts of the disputes, is printed on stderr
+    /// as with `verbose`. Unknown options and more than one file fail with [`EngineError::Config`].
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self, EngineError> {
         args.next();
 
-        let filename = match args.next() {
-            Some(arg) => arg,
-            None => return Err(Box::new(ConfigFileError(String::from("Didn't get a file name")))),
+        let mut filename = None;
+        let mut output = None;
+        let mut client_filter = None;
+        let mut self_check = false;
+        let mut verbose = false;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--clients" => {
+                    let clients = args.next()
+                        .ok_or_else(|| EngineError::Config(String::from("Missing client ids after --clients")))?;
+                    client_filter = Some(parse_clients(&clients)?);
+                }
+                "-o" | "--output" => {
+                    output = Some(args.next()
+                        .ok_or_else(|| EngineError::Config(format!("Missing filename after {}", arg)))?);
+                }
+                "--round-trip" => self_check = true,
+                "--stats" => verbose = true,
+                _ if arg.starts_with('-') => return Err(EngineError::Config(format!("Unknown option {}", arg))),
+                _ if filename.is_some() => return Err(EngineError::Config(format!("Unexpected argument {}", arg))),
+                _ => filename = Some(arg),
+            }
+        }
+
+        let reader: Box<dyn io::Read> = match filename {
+            Some(filename) => open_file(&filename)?,
+            None => Box::new(io::stdin()),
+        };
+        let writer: Box<dyn io::Write> = match output {
+            Some(output) => Box::new(File::create(output)?),
+            None => Box::new(io::stdout()),
         };
-        let reader = File::open(filename)?;
-        let writer = io::stdout();
 
-        Ok(Config { reader, writer })
+        Ok(ConfigBuilder::new()
+            .reader(reader)
+            .writer(writer)
+            .client_filter(client_filter)
+            .self_check(self_check)
+            .verbose(verbose)
+            .build())
     }
 }
 
-#[derive(Debug)]
-struct ConfigFileError(String);
+impl Options {
+    /// The options of the csv reader.
+    fn reader_options(&self) -> ReaderOptions {
+        // The amounts are only rounded when written, so that the extra decimals can round them up.
+        let precision = match self.rounding_mode {
+            RoundingMode::Truncate => self.precision,
+            RoundingMode::HalfUp | RoundingMode::HalfEven => MAX_PRECISION,
+        };
+        ReaderOptions {
+            error_policy: self.error_policy,
+            precision,
+            delimiter: self.csv_delimiter(),
+            amount_format: self.amount_format,
+            has_headers: self.has_headers,
+            max_transactions: self.max_transactions,
+            max_field_len: self.max_field_len,
+            column_mapping: self.column_mapping.clone(),
+            comment_char: self.comment_char,
+            skip_unknown_types: self.skip_unknown_types,
+            strict_amounts: self.strict_amounts,
+            empty_amount_policy: self.empty_amount_policy,
+            input_format: self.input_format,
+            trim: self.trim,
+        }
+    }
 
-impl std::error::Error for ConfigFileError {}
+    /// The options of the client account writers.
+    fn writer_options(&self) -> WriterOptions {
+        WriterOptions {
+            precision: self.precision,
+            rounding_mode: self.rounding_mode,
+            include_tx_count: self.include_tx_count,
+            include_locked_by: self.include_locked_by,
+            include_last_tx: self.include_last_tx,
+            include_totals: self.include_totals,
+            delimiter: self.csv_delimiter(),
+        }
+    }
 
-impl fmt::Display for ConfigFileError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.0)
+    /// The delimiter of the csv files, semicolons instead of the default commas with
+    /// [`AmountFormat::CommaDecimal`] as the commas separate the decimals.
+    fn csv_delimiter(&self) -> u8 {
+        match (self.amount_format, self.delimiter) {
+            (AmountFormat::CommaDecimal, b',') => b';',
+            (_, delimiter) => delimiter,
+        }
+    }
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            output_format: OutputFormat::default(),
+            error_policy: ErrorPolicy::default(),
+            precision: DEFAULT_PRECISION,
+            verbose: false,
+            output_order: OutputOrder::default(),
+            include_tx_count: false,
+            include_locked_by: false,
+            include_last_tx: false,
+            include_totals: false,
+            client_filter: None,
+            delimiter: b',',
+            reserved_client_ids: HashSet::new(),
+            rounding_mode: RoundingMode::default(),
+            amount_format: AmountFormat::default(),
+            on_transaction: None,
+            has_headers: true,
+            max_transactions: None,
+            max_field_len: DEFAULT_MAX_FIELD_LEN,
+            column_mapping: ColumnMapping::default(),
+            disputes_expected: true,
+            journal_writer: None,
+            on_hold: None,
+            comment_char: None,
+            skip_unknown_types: false,
+            resume_at: None,
+            strict_amounts: false,
+            self_check: false,
+            dispute_window: None,
+            empty_amount_policy: EmptyAmountPolicy::default(),
+            input_format: InputFormat::default(),
+            max_clients: None,
+            overdraw_policy: OverdrawPolicy::default(),
+            trim: Trim::default(),
+        }
+    }
+}
+
+impl<R: io::Read, W: io::Write> Config<R, W> {
+    /// Replace the reader of the transactions with the one mapped from it.
+    fn map_reader<R2: io::Read>(self, map: impl FnOnce(R) -> R2) -> Config<R2, W> {
+        Config { reader: map(self.reader), writer: self.writer, options: self.options }
+    }
+
+    /// Replace the writer of the client accounts with the one mapped from it.
+    fn map_writer<W2: io::Write>(self, map: impl FnOnce(W) -> W2) -> Config<R, W2> {
+        Config { reader: self.reader, writer: map(self.writer), options: self.options }
+    }
+}
+
+/// Builds a config, with default options, reading from stdin and writing to stdout.
+///
+/// ```
+/// use payments_engine_rs::{ConfigBuilder, OutputFormat, run};
+/// let reader = "type,client,tx,amount\ndeposit,1,1,1.23456".as_bytes();
+/// let config = ConfigBuilder::new()
+///     .reader(reader)
+///     .writer(vec![])
+///     .output_format(OutputFormat::Csv)
+///     .precision(2)
+///     .build();
+/// let writer = run(config).unwrap();
+/// assert_eq!(String::from_utf8(writer).unwrap(), "client,available,held,total,locked\n1,1.23,0.00,1.23,false\n");
+/// ```
+pub struct ConfigBuilder<R: io::Read, W: io::Write> {
+    config: Config<R, W>,
+}
+
+impl ConfigBuilder<io::Stdin, io::Stdout> {
+    /// Create a builder of a config with the default options, reading the transactions from stdin
+    /// and writing the client accounts to stdout.
+    pub fn new() -> ConfigBuilder<io::Stdin, io::Stdout> {
+        ConfigBuilder {
+            config: Config { reader: io::stdin(), writer: io::stdout(), options: Options::default() },
+        }
+    }
+}
+
+impl Default for ConfigBuilder<io::Stdin, io::Stdout> {
+    fn default() -> ConfigBuilder<io::Stdin, io::Stdout> {
+        ConfigBuilder::new()
+    }
+}
+
+impl<R: io::Read, W: io::Write> ConfigBuilder<R, W> {
+    /// Set the reader of the transactions.
+    pub fn reader<R2: io::Read>(self, reader: R2) -> ConfigBuilder<R2, W> {
+        ConfigBuilder { config: self.config.map_reader(|_| reader) }
+    }
+
+    /// Set the writer of the client accounts.
+    pub fn writer<W2: io::Write>(self, writer: W2) -> ConfigBuilder<R, W2> {
+        ConfigBuilder { config: self.config.map_writer(|_| writer) }
+    }
+
+    /// Set the format in which the client accounts are written.
+    pub fn output_format(mut self, output_format: OutputFormat) -> ConfigBuilder<R, W> {
+        self.config.options.output_format = output_format;
+        self
+    }
+
+    /// Set the policy for the records that can't be parsed.
+    pub fn error_policy(mut self, error_policy: ErrorPolicy) -> ConfigBuilder<R, W> {
+        self.config.options.error_policy = error_policy;
+        self
+    }
+
+    /// Set the number of decimals of the amounts.
+    pub fn precision(mut self, precision: u32) -> ConfigBuilder<R, W> {
+        self.config.options.precision = precision;
+        self
+    }
+
+    /// Set whether a summary of the run is printed on stderr.
+    pub fn verbose(mut self, verbose: bool) -> ConfigBuilder<R, W> {
+        self.config.options.verbose = verbose;
+        self
+    }
+
+    /// Set the order in which the client accounts are written.
+    pub fn output_order(mut self, output_order: OutputOrder) -> ConfigBuilder<R, W> {
+        self.config.options.output_order = output_order;
+        self
+    }
+
+    /// Set whether the client accounts are written with an extra `tx_count` column.
+    pub fn include_tx_count(mut self, include_tx_count: bool) -> ConfigBuilder<R, W> {
+        self.config.options.include_tx_count = include_tx_count;
+        self
+    }
+
+    /// Set whether the client accounts are written with an extra `locked_by` column.
+    pub fn include_locked_by(mut self, include_locked_by: bool) -> ConfigBuilder<R, W> {
+        self.config.options.include_locked_by = include_locked_by;
+        self
+    }
+
+    /// Set whether the client accounts are written with an extra `last_tx` column.
+    pub fn include_last_tx(mut self, include_last_tx: bool) -> ConfigBuilder<R, W> {
+        self.config.options.include_last_tx = include_last_tx;
+        self
+    }
+
+    /// Set whether a final `TOTAL` row is written, in the csv and table formats only.
+    pub fn include_totals(mut self, include_totals: bool) -> ConfigBuilder<R, W> {
+        self.config.options.include_totals = include_totals;
+        self
+    }
+
+    /// Set the clients whose accounts are written, if any.
+    pub fn client_filter(mut self, client_filter: Option<HashSet<ClientId>>) -> ConfigBuilder<R, W> {
+        self.config.options.client_filter = client_filter;
+        self
+    }
+
+    /// Set the delimiter of the csv files, read and written.
+    pub fn delimiter(mut self, delimiter: u8) -> ConfigBuilder<R, W> {
+        self.config.options.delimiter = delimiter;
+        self
     }
+
+    /// Set the clients whose transactions are ignored.
+    pub fn reserved_client_ids(mut self, reserved_client_ids: HashSet<ClientId>) -> ConfigBuilder<R, W> {
+        self.config.options.reserved_client_ids = reserved_client_ids;
+        self
+    }
+
+    /// Set the format of the read amounts.
+    pub fn amount_format(mut self, amount_format: AmountFormat) -> ConfigBuilder<R, W> {
+        self.config.options.amount_format = amount_format;
+        self
+    }
+
+    /// Set the callback invoked after each processed transaction with its outcome.
+    pub fn on_transaction<F: FnMut(&Transaction, &ProcessedOutcome) + 'static>(mut self, on_transaction: F) -> ConfigBuilder<R, W> {
+        self.config.options.on_transaction = Some(Box::new(on_transaction));
+        self
+    }
+
+    /// Set the journal to which each applied transaction is written as a JSON line.
+    pub fn journal_writer<J: io::Write + 'static>(mut self, journal_writer: J) -> ConfigBuilder<R, W> {
+        self.config.options.journal_writer = Some(Box::new(journal_writer));
+        self
+    }
+
+    /// Set the hook adjusting the amounts released by the resolves.
+    pub fn on_hold<F: Fn(Amount) -> Amount + Send + 'static>(mut self, on_hold: F) -> ConfigBuilder<R, W> {
+        self.config.options.on_hold = Some(Box::new(on_hold));
+        self
+    }
+
+    /// Set the first character of the lines of the csv file to skip, if any.
+    pub fn comment_char(mut self, comment_char: Option<u8>) -> ConfigBuilder<R, W> {
+        self.config.options.comment_char = comment_char;
+        self
+    }
+
+    /// Set whether the records of unknown types are skipped.
+    pub fn skip_unknown_types(mut self, skip_unknown_types: bool) -> ConfigBuilder<R, W> {
+        self.config.options.skip_unknown_types = skip_unknown_types;
+        self
+    }
+
+    /// Set the byte offset at which the reading is resumed with [`run_seekable`], if any.
+    pub fn resume_at(mut self, resume_at: Option<u64>) -> ConfigBuilder<R, W> {
+        self.config.options.resume_at = resume_at;
+        self
+    }
+
+    /// Set whether the disputes, resolves and chargebacks with an amount can't be parsed.
+    pub fn strict_amounts(mut self, strict_amounts: bool) -> ConfigBuilder<R, W> {
+        self.config.options.strict_amounts = strict_amounts;
+        self
+    }
+
+    /// Set whether the client accounts are checked before being written.
+    pub fn self_check(mut self, self_check: bool) -> ConfigBuilder<R, W> {
+        self.config.options.self_check = self_check;
+        self
+    }
+
+    /// Set the number of transactions after which the deposits and withdrawals can't be disputed
+    /// anymore, if any.
+    pub fn dispute_window(mut self, dispute_window: Option<u64>) -> ConfigBuilder<R, W> {
+        self.config.options.dispute_window = dispute_window;
+        self
+    }
+
+    /// Set how the deposits and withdrawals with an empty amount are handled.
+    pub fn empty_amount_policy(mut self, empty_amount_policy: EmptyAmountPolicy) -> ConfigBuilder<R, W> {
+        self.config.options.empty_amount_policy = empty_amount_policy;
+        self
+    }
+
+    /// Set the format of the transactions.
+    pub fn input_format(mut self, input_format: InputFormat) -> ConfigBuilder<R, W> {
+        self.config.options.input_format = input_format;
+        self
+    }
+
+    /// Set the maximum number of client accounts, if any.
+    pub fn max_clients(mut self, max_clients: Option<usize>) -> ConfigBuilder<R, W> {
+        self.config.options.max_clients = max_clients;
+        self
+    }
+
+    /// Set how the withdrawals exceeding the available funds are handled.
+    pub fn overdraw_policy(mut self, overdraw_policy: OverdrawPolicy) -> ConfigBuilder<R, W> {
+        self.config.options.overdraw_policy = overdraw_policy;
+        self
+    }
+
+    /// Set the parts of the csv file of the transactions whose fields are trimmed.
+    pub fn trim(mut self, trim: Trim) -> ConfigBuilder<R, W> {
+        self.config.options.trim = trim;
+        self
+    }
+
+    /// Set whether the csv file of the transactions starts with a header.
+    pub fn has_headers(mut self, has_headers: bool) -> ConfigBuilder<R, W> {
+        self.config.options.has_headers = has_headers;
+        self
+    }
+
+    /// Set the number of transactions after which the reading stops, if any.
+    pub fn max_transactions(mut self, max_transactions: Option<u64>) -> ConfigBuilder<R, W> {
+        self.config.options.max_transactions = max_transactions;
+        self
+    }
+
+    /// Set the maximum length of the fields of the csv file in bytes.
+    pub fn max_field_len(mut self, max_field_len: usize) -> ConfigBuilder<R, W> {
+        self.config.options.max_field_len = max_field_len;
+        self
+    }
+
+    /// Set the names of the columns in the header of the csv file.
+    pub fn column_mapping(mut self, column_mapping: ColumnMapping) -> ConfigBuilder<R, W> {
+        self.config.options.column_mapping = column_mapping;
+        self
+    }
+
+    /// Set whether the deposits and withdrawals are kept in memory to be disputed.
+    pub fn disputes_expected(mut self, disputes_expected: bool) -> ConfigBuilder<R, W> {
+        self.config.options.disputes_expected = disputes_expected;
+        self
+    }
+
+    /// Set how the amounts are rounded to the precision.
+    pub fn rounding_mode(mut self, rounding_mode: RoundingMode) -> ConfigBuilder<R, W> {
+        self.config.options.rounding_mode = rounding_mode;
+        self
+    }
+
+    /// Build the config.
+    pub fn build(self) -> Config<R, W> {
+        self.config
+    }
+}
+
+/// Parse comma separated client ids.
+fn parse_clients(clients: &str) -> Result<HashSet<ClientId>, EngineError> {
+    clients.split(',')
+        .map(|client| client.trim().parse()
+            .map_err(|_| EngineError::Config(format!("Invalid client id {}", client))))
+        .collect()
+}
+
+/// Open the file to read, decompressing it if it is gzipped.
+#[cfg(feature = "gzip")]
+fn open_file(filename: &str) -> Result<Box<dyn io::Read>, EngineError> {
+    let file = File::open(filename)?;
+    if filename.ends_with(".gz") {
+        return Ok(Box::new(flate2::read::GzDecoder::new(file)));
+    }
+    Ok(Box::new(file))
+}
+
+/// Open the file to read.
+#[cfg(not(feature = "gzip"))]
+fn open_file(filename: &str) -> Result<Box<dyn io::Read>, EngineError> {
+    Ok(Box::new(File::open(filename)?))
 }
 
 /// Run the payments engine with the given configuration.
-pub fn run<R: io::Read, W: io::Write>(config: Config<R, W>) -> Result<W, Box<dyn Error>> {
+pub fn run<R: io::Read, W: io::Write>(mut config: Config<R, W>) -> Result<W, EngineError> {
+    if config.options.resume_at.is_some() {
+        return Err(EngineError::Config(String::from("Resuming at an offset requires a seekable reader, see run_seekable")));
+    }
+    let reader_options = config.options.reader_options();
+    let writer_options = config.options.writer_options();
+    let mut transaction_manager = TransactionManager::new()
+        .with_reserved_client_ids(config.options.reserved_client_ids)
+        .with_disputes_expected(config.options.disputes_expected)
+        .with_dispute_window(config.options.dispute_window)
+        .with_max_clients(config.options.max_clients)
+        .with_overdraw_policy(config.options.overdraw_policy);
+    if let Some(on_hold) = config.options.on_hold {
+        transaction_manager = transaction_manager.with_on_hold(on_hold);
+    }
+    let mut journal_writer = config.options.journal_writer.map(JournalWriter::new);
+    process_reader(&mut transaction_manager, config.reader, reader_options, config.options.on_transaction.as_mut(), journal_writer.as_mut())?;
+    if let Some(journal_writer) = journal_writer {
+        journal_writer.into_inner()?;
+    }
+    if config.options.verbose {
+        eprintln!("{}", summary(slice::from_ref(&transaction_manager)));
+    }
+    let mut client_accounts = sorted_accounts(&transaction_manager, config.options.output_order);
+    filter_accounts(&mut client_accounts, config.options.client_filter.as_ref());
+    if config.options.self_check {
+        return write_checked_accounts(&client_accounts, config.writer, config.options.output_format, writer_options);
+    }
+    write_accounts(&client_accounts, config.writer, config.options.output_format, writer_options)
+}
+
+/// Run the payments engine with the given configuration, resuming the reading of the transactions
+/// at the byte offset `resume_at`, if any, e.g. to process a large file in chunks.
+///
+/// The offset must be the start of a record, e.g. the byte offset of the first record not processed
+/// by a previous run. The header, if any, is still read from the start of the reader, and the
+/// records before the offset are skipped without being parsed. The lines of the errors are then
+/// counted from the offset, the header being the first line.
+///
+/// ```
+/// use std::io::Cursor;
+/// use payments_engine_rs::{ConfigBuilder, run_seekable};
+/// let reader = Cursor::new("type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,2.0\n");
+/// let config = ConfigBuilder::new().reader(reader).writer(vec![]).resume_at(Some(38)).build();
+/// let writer = run_seekable(config).unwrap();
+/// assert_eq!(String::from_utf8(writer).unwrap(), "client,available,held,total,locked\n1,2.0000,0.0000,2.0000,false\n");
+/// ```
+pub fn run_seekable<R: io::Read + io::Seek, W: io::Write>(mut config: Config<R, W>) -> Result<W, EngineError> {
+    let Some(resume_at) = config.options.resume_at.take() else {
+        return run(config);
+    };
+    let (has_headers, comment_char) = (config.options.has_headers, config.options.comment_char);
+    let mut reader = io::BufReader::new(&mut config.reader);
+
+    // The header, preceded by its comments if any, is read again before the records at the offset.
+    let mut header = Vec::new();
+    if has_headers {
+        loop {
+            let start = header.len();
+            if reader.read_until(b'\n', &mut header)? == 0 || comment_char != header.get(start).copied() {
+                break;
+            }
+        }
+    }
+    drop(reader);
+    config.reader.seek(io::SeekFrom::Start(resume_at.max(header.len() as u64)))?;
+    run(config.map_reader(|reader| io::Cursor::new(header).chain(reader)))
+}
+
+/// Run the payments engine with the given configuration and compute the SHA-256 checksum of the
+/// written client accounts, e.g. to verify the integrity of the output downstream.
+///
+/// The client accounts are written as by [`run`], in a deterministic order, so the same
+/// transactions always give the same checksum.
+#[cfg(feature = "checksum")]
+pub fn run_with_checksum<R: io::Read, W: io::Write>(config: Config<R, W>) -> Result<(W, [u8; 32]), EngineError> {
+    let writer = run(config.map_writer(checksum_writer::ChecksumWriter::new))?;
+    Ok(writer.into_inner())
+}
+
+/// Run the payments engine over several readers processed in sequence and write the client
+/// accounts to the writer.
+///
+/// All the readers share the same client accounts, so a transaction can reference a transaction
+/// from a previous reader. Each reader must start with its own header.
+pub fn run_many<R: io::Read, W: io::Write>(readers: Vec<R>, writer: W) -> Result<W, EngineError> {
+    let mut transaction_manager = TransactionManager::new();
+    for reader in readers {
+        process_reader(&mut transaction_manager, reader, ReaderOptions::default(), None, None)?;
+    }
+    let client_accounts = sorted_accounts(&transaction_manager, OutputOrder::ClientIdAscending);
+    write_accounts(&client_accounts, writer, OutputFormat::Csv, WriterOptions::default())
+}
+
+/// Run the payments engine over an async stream of transactions and write the client accounts
+/// to the writer once the stream ends.
+///
+/// The first error of the stream stops the processing and is returned.
+#[cfg(feature = "async")]
+pub async fn run_async<S, W>(stream: S, writer: W) -> Result<W, EngineError>
+where
+    S: futures::Stream<Item = Result<Transaction, EngineError>>,
+    W: io::Write,
+{
+    use futures::StreamExt;
+
+    let mut transaction_manager = TransactionManager::new();
+    let mut stream = std::pin::pin!(stream);
+    while let Some(transaction) = stream.next().await {
+        transaction_manager.process_transaction(transaction?)?;
+    }
+    let client_accounts = sorted_accounts(&transaction_manager, OutputOrder::ClientIdAscending);
+    write_accounts(&client_accounts, writer, OutputFormat::Csv, WriterOptions::default())
+}
+
+/// Run the payments engine with the given configuration on several threads.
+///
+/// The transactions are read on the current thread and dispatched to `num_threads` workers
+/// according to their client id. As the balance of a client only depends on its own transactions,
+/// each worker owns the client accounts of its partition. The order of the transactions of a client
+/// is preserved, and the transaction ids reused across partitions are detected while dispatching, so
+/// the output is identical to [`run`].
+///
+/// These options aren't supported and fail with [`EngineError::Config`]:
+///
+/// * `on_transaction`, `journal_writer` and `on_hold`, as the transactions are processed on the
+///   workers.
+/// * `resume_at`, as it requires a seekable reader, see [`run_seekable`].
+/// * `dispute_window`, as the sequence numbers of the transactions only follow the order of the
+///   transactions of each worker.
+/// * `max_clients`, as each worker only knows the client accounts of its partition.
+pub fn run_parallel<R: io::Read, W: io::Write>(config: Config<R, W>, num_threads: usize) -> Result<W, EngineError> {
+    if config.options.on_transaction.is_some() {
+        return Err(EngineError::Config(String::from("The on_transaction callback isn't supported by run_parallel")));
+    }
+    if config.options.journal_writer.is_some() {
+        return Err(EngineError::Config(String::from("The journal writer isn't supported by run_parallel")));
+    }
+    if config.options.on_hold.is_some() {
+        return Err(EngineError::Config(String::from("The on_hold hook isn't supported by run_parallel")));
+    }
+    if config.options.resume_at.is_some() {
+        return Err(EngineError::Config(String::from("Resuming at an offset isn't supported by run_parallel")));
+    }
+    if config.options.dispute_window.is_some() {
+        return Err(EngineError::Config(String::from("The dispute window isn't supported by run_parallel")));
+    }
+    if config.options.max_clients.is_some() {
+        return Err(EngineError::Config(String::from("The maximum number of clients isn't supported by run_parallel")));
+    }
+    let num_threads = num_threads.max(1);
+    let reader_options = config.options.reader_options();
+    let writer_options = config.options.writer_options();
+    let reader = config.reader;
+    let mut first_seen = HashMap::new();
+    let mut seen_tx = HashSet::new();
+
+    let transaction_managers = thread::scope(|scope| -> Result<Vec<TransactionManager>, EngineError> {
+        let mut senders = Vec::with_capacity(num_threads);
+        let mut workers = Vec::with_capacity(num_threads);
+        for _ in 0..num_threads {
+            // Bounded channels avoid reading the whole file in memory if the workers are slower.
+            let (sender, receiver) = mpsc::sync_channel::<(Transaction, Option<IgnoreReason>)>(1024);
+            senders.push(sender);
+            let reserved_client_ids = config.options.reserved_client_ids.clone();
+            let disputes_expected = config.options.disputes_expected;
+            let overdraw_policy = config.options.overdraw_policy;
+            workers.push(scope.spawn(move || -> Result<TransactionManager, EngineError> {
+                let mut transaction_manager = TransactionManager::new()
+                    .with_reserved_client_ids(reserved_client_ids)
+                    .with_disputes_expected(disputes_expected)
+                    .with_overdraw_policy(overdraw_policy);
+                for (transaction, ignore_reason) in receiver {
+                    match ignore_reason {
+                        Some(reason) => transaction_manager.ignore_transaction(transaction, reason),
+                        None => transaction_manager.process_transaction(transaction)?,
+                    };
+                }
+                Ok(transaction_manager)
+            }));
+        }
+
+        let mut transaction_reader = TransactionReader::new(reader, reader_options);
+        while let Some(transaction) = transaction_reader.next()? {
+            // The workers only know the order of their own clients.
+            let clients_seen = first_seen.len() as u64;
+            first_seen.entry(transaction.client()).or_insert(clients_seen);
+            // The workers only know the transaction ids of their own clients.
+            let ignore_reason = match transaction.amount() {
+                Some(_) if !seen_tx.insert(transaction.tx()) => Some(IgnoreReason::DuplicateTransaction),
+                _ => None,
+            };
+            let partition = transaction.client() as usize % num_threads;
+            // The worker only hangs up on errors, which are returned when joining it.
+            if senders[partition].send((transaction, ignore_reason)).is_err() {
+                break;
+            }
+        }
+        if transaction_reader.limit_reached() {
+            eprintln!("{}", LIMIT_REACHED);
+        }
+        // Close the channels so the workers finish.
+        drop(senders);
+
+        workers.into_iter()
+            .map(|worker| worker.join().unwrap_or_else(|payload| panic::resume_unwind(payload)))
+            .collect()
+    })?;
+
+    if config.options.verbose {
+        eprintln!("{}", summary(&transaction_managers));
+    }
+    let client_accounts: Vec<ClientAccount> = transaction_managers.into_iter()
+        .flat_map(|transaction_manager| transaction_manager.client_account_index.into_values())
+        .map(|client_account| ClientAccount { first_seen: first_seen[&client_account.client], ..client_account })
+        .collect();
+    let mut client_accounts: Vec<&ClientAccount> = client_accounts.iter().collect();
+    sort_accounts(&mut client_accounts, config.options.output_order);
+    filter_accounts(&mut client_accounts, config.options.client_filter.as_ref());
+
+    if config.options.self_check {
+        return write_checked_accounts(&client_accounts, config.writer, config.options.output_format, writer_options);
+    }
+    write_accounts(&client_accounts, config.writer, config.options.output_format, writer_options)
+}
+
+/// Run the payments engine over the given reader and return the client accounts sorted by
+/// client id, with the warnings about their balances.
+pub fn run_to_accounts<R: io::Read>(reader: R) -> Result<AccountsReport, EngineError> {
     let mut transaction_manager = TransactionManager::new();
-    let mut csv_reader = csv_reader::CsvReader::new(config.reader);
+    process_reader(&mut transaction_manager, reader, ReaderOptions::default(), None, None)?;
+    let accounts = into_sorted_accounts(transaction_manager, OutputOrder::ClientIdAscending);
+    let warnings = negative_balance_warnings(&accounts);
+    Ok(AccountsReport { accounts, warnings })
+}
+
+/// The client accounts returned by [`run_to_accounts`].
+#[derive(Debug)]
+pub struct AccountsReport {
+    pub accounts: Vec<ClientAccount>, // Client accounts sorted by client id
+    pub warnings: Vec<NegativeBalanceWarning>, // Accounts with negative available funds that aren't locked
+}
+
+/// An account with negative available funds that isn't locked.
+///
+/// The available funds of an account can legitimately become negative when a deposit that was
+/// already withdrawn is charged back, but then the account is locked. On an account that isn't
+/// locked, e.g. with a pending dispute of such a deposit, it usually signals an issue upstream,
+/// unless the available funds are within the credit limit of the account.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NegativeBalanceWarning {
+    pub client: ClientId, // Client id
+    pub available: Amount,
+}
+
+/// Collect the accounts with negative available funds, beyond their credit limit, that aren't locked.
+pub fn negative_balance_warnings<A: Borrow<ClientAccount>>(client_accounts: &[A]) -> Vec<NegativeBalanceWarning> {
+    client_accounts.iter()
+        .map(Borrow::borrow)
+        .filter(|client_account| client_account.available.saturating_add(client_account.credit_limit) < Amount::ZERO)
+        .filter(|client_account| !client_account.locked)
+        .map(|client_account| NegativeBalanceWarning { client: client_account.client, available: client_account.available })
+        .collect()
+}
+
+/// An inconsistency of a client account found by [`self_check`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConsistencyError {
+    /// The total funds, available and held, can't be represented.
+    TotalOverflow { client: ClientId },
+    /// The held funds are negative.
+    NegativeHeld { client: ClientId, held: Amount },
+    /// The account is locked with held funds.
+    LockedWithHeld { client: ClientId, held: Amount },
+    /// The written total funds aren't the sum of the written available and held funds.
+    TotalMismatch { client: ClientId, total: Amount },
+    /// The written client accounts can't be read back.
+    Unreadable(String),
+}
+
+impl fmt::Display for ConsistencyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConsistencyError::TotalOverflow { client } => write!(f, "the total funds of the client account {} overflow", client),
+            ConsistencyError::NegativeHeld { client, held } => write!(f, "the client account {} has negative held funds {}", client, held),
+            ConsistencyError::LockedWithHeld { client, held } => write!(f, "the locked client account {} has held funds {}", client, held),
+            ConsistencyError::TotalMismatch { client, total } => {
+                write!(f, "the written total funds {} of the client account {} aren't its available and held funds", total, client)
+            }
+            ConsistencyError::Unreadable(error) => write!(f, "the written client accounts can't be read back: {}", error),
+        }
+    }
+}
+
+/// Check the consistency of the client accounts, e.g. the written ones to catch regressions: their
+/// total funds are the sum of their available and held funds, their held funds aren't negative, and
+/// the locked accounts have no held funds.
+///
+/// A partial chargeback, or a chargeback while another transaction of the client is disputed,
+/// locks the account with held funds, which is then reported as well.
+pub fn self_check<A: Borrow<ClientAccount>>(client_accounts: &[A]) -> Result<(), Vec<ConsistencyError>> {
+    let mut errors = Vec::new();
+    for client_account in client_accounts.iter().map(Borrow::borrow) {
+        if client_account.total().is_none() {
+            errors.push(ConsistencyError::TotalOverflow { client: client_account.client });
+        }
+        check_held(client_account.client, client_account.held, client_account.locked, &mut errors);
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Check the consistency of the client accounts as written, read back from the output, as
+/// [`self_check`] does with the client accounts in memory. The written total funds must also be
+/// the sum of the written available and held funds, which e.g. the rounding may break.
+fn self_check_written(client_accounts: &[AccountSummary]) -> Result<(), Vec<ConsistencyError>> {
+    let mut errors = Vec::new();
+    for client_account in client_accounts {
+        let client = client_account.client;
+        match client_account.available.checked_add(client_account.held) {
+            None => errors.push(ConsistencyError::TotalOverflow { client }),
+            Some(total) if total != client_account.total => {
+                errors.push(ConsistencyError::TotalMismatch { client, total: client_account.total });
+            }
+            Some(_) => {}
+        }
+        check_held(client, client_account.held, client_account.locked, &mut errors);
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Check that the held funds aren't negative, nor held on a locked account.
+fn check_held(client: ClientId, held: Amount, locked: bool, errors: &mut Vec<ConsistencyError>) {
+    if held < Amount::ZERO {
+        errors.push(ConsistencyError::NegativeHeld { client, held });
+    } else if locked && held != Amount::ZERO {
+        errors.push(ConsistencyError::LockedWithHeld { client, held });
+    }
+}
+
+/// The result of the validation of a csv file by [`validate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationReport {
+    pub valid: u64, // Number of transactions parsed and applied
+    pub skipped: Vec<SkippedRecord>, // Records that couldn't be parsed
+    pub ignored: Vec<IgnoredTransaction>, // Transactions parsed but ignored, with the reasons
+}
 
+/// Parse and apply all the transactions of the reader without writing anything, and report the
+/// records that couldn't be parsed and the transactions that were ignored.
+pub fn validate<R: io::Read>(reader: R) -> Result<ValidationReport, EngineError> {
+    let mut transaction_manager = TransactionManager::new();
+    let reader_options = ReaderOptions { error_policy: ErrorPolicy::SkipBad, ..ReaderOptions::default() };
+    let mut csv_reader = csv_reader::CsvReader::new(reader, reader_options);
     while let Some(transaction) = csv_reader.next()? {
         transaction_manager.process_transaction(transaction)?;
     }
 
-    let mut csv_writer = csv_writer::CsvWriter::new(config.writer);
-    for (_, client_account) in transaction_manager.client_account_index.iter() {
-        csv_writer.write(&client_account)?;
+    let ignored = transaction_manager.ignored().to_vec();
+    Ok(ValidationReport {
+        valid: transaction_manager.processed() - ignored.len() as u64,
+        skipped: csv_reader.skipped().to_vec(),
+        ignored,
+    })
+}
+
+/// Process all the transactions of the reader.
+///
+/// The records skipped according to the error policy, and the maximum number of transactions
+/// if it is reached, are reported on stderr.
+fn process_reader<R: io::Read>(
+    transaction_manager: &mut TransactionManager,
+    reader: R,
+    reader_options: ReaderOptions,
+    mut on_transaction: Option<&mut TransactionCallback>,
+    mut journal_writer: Option<&mut JournalWriter<Box<dyn io::Write>>>,
+) -> Result<(), EngineError> {
+    let mut transaction_reader = TransactionReader::new(reader, reader_options);
+
+    if on_transaction.is_none() && journal_writer.is_none() {
+        while let Some(transaction) = transaction_reader.next()? {
+            transaction_manager.process_transaction(transaction)?;
+        }
+    } else {
+        while let Some(mut transaction) = transaction_reader.next()? {
+            let before = Balances::of(transaction_manager, transaction.client());
+            // The transactions don't own any heap memory, so copying them is cheap.
+            let outcome = transaction_manager.process_transaction(transaction.clone())?;
+            transaction.set_seq(transaction_manager.processed());
+            if let Some(journal_writer) = journal_writer.as_mut() {
+                if !matches!(outcome, ProcessedOutcome::Ignored(_)) {
+                    journal_writer.write(&transaction, before, Balances::of(transaction_manager, transaction.client()))?;
+                }
+            }
+            if let Some(on_transaction) = on_transaction.as_mut() {
+                on_transaction(&transaction, &outcome);
+            }
+        }
+    }
+
+    for skipped in transaction_reader.skipped() {
+        eprintln!("Skipped record at line {}: {}", skipped.line, skipped.error);
+    }
+    if transaction_reader.limit_reached() {
+        eprintln!("{}", LIMIT_REACHED);
+    }
+
+    Ok(())
+}
+
+/// The reader of the transactions in the input format of the options.
+enum TransactionReader<R: io::Read> {
+    Csv(csv_reader::CsvReader<R>),
+    Jsonl(jsonl_reader::JsonlReader<R>),
+}
+
+impl<R: io::Read> TransactionReader<R> {
+    fn new(reader: R, options: ReaderOptions) -> TransactionReader<R> {
+        match options.input_format {
+            InputFormat::Csv => TransactionReader::Csv(csv_reader::CsvReader::new(reader, options)),
+            InputFormat::Jsonl => TransactionReader::Jsonl(jsonl_reader::JsonlReader::new(reader, options)),
+        }
+    }
+
+    fn next(&mut self) -> Result<Option<Transaction>, EngineError> {
+        match self {
+            TransactionReader::Csv(csv_reader) => csv_reader.next(),
+            TransactionReader::Jsonl(jsonl_reader) => jsonl_reader.next(),
+        }
+    }
+
+    fn skipped(&self) -> &[SkippedRecord] {
+        match self {
+            TransactionReader::Csv(csv_reader) => csv_reader.skipped(),
+            TransactionReader::Jsonl(jsonl_reader) => jsonl_reader.skipped(),
+        }
+    }
+
+    fn limit_reached(&self) -> bool {
+        match self {
+            TransactionReader::Csv(csv_reader) => csv_reader.limit_reached(),
+            TransactionReader::Jsonl(jsonl_reader) => jsonl_reader.limit_reached(),
+        }
+    }
+}
+
+/// The warning when the reading stops at the maximum number of transactions.
+const LIMIT_REACHED: &str = "Stopped reading at the maximum number of transactions";
+
+/// Summarize the processing of the transaction managers in a single line.
+fn summary(transaction_managers: &[TransactionManager]) -> String {
+    let processed: u64 = transaction_managers.iter().map(TransactionManager::processed).sum();
+    let clients: usize = transaction_managers.iter().map(|manager| manager.client_account_index.len()).sum();
+    let ignored: usize = transaction_managers.iter().map(|manager| manager.ignored().len()).sum();
+    let held = transaction_managers.iter()
+        .fold(Amount::ZERO, |held, manager| held.saturating_add(manager.total_held()));
+    let disputes = transaction_managers.iter().map(TransactionManager::dispute_stats)
+        .fold(DisputeStats::default(), |disputes, stats| DisputeStats {
+            opened: disputes.opened + stats.opened,
+            resolved: disputes.resolved + stats.resolved,
+            charged_back: disputes.charged_back + stats.charged_back,
+        });
+    format!(
+        "processed {} transactions for {} clients, {} ignored, {} held, {} disputes opened, {} resolved, {} charged back",
+        format_count(processed), format_count(clients as u64), format_count(ignored as u64), held,
+        format_count(disputes.opened), format_count(disputes.resolved), format_count(disputes.charged_back)
+    )
+}
+
+/// Format the count with commas as thousands separators.
+fn format_count(count: u64) -> String {
+    let digits = count.to_string();
+    let mut formatted = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            formatted.push(',');
+        }
+        formatted.push(digit);
+    }
+    formatted
+}
+
+/// The client accounts of the transaction manager sorted in the given order.
+fn sorted_accounts(transaction_manager: &TransactionManager, output_order: OutputOrder) -> Vec<&ClientAccount> {
+    let mut client_accounts: Vec<&ClientAccount> = transaction_manager.accounts().collect();
+    if output_order != OutputOrder::ClientIdAscending {
+        sort_accounts(&mut client_accounts, output_order);
+    }
+    client_accounts
+}
+
+/// Extract the client accounts of the transaction manager sorted in the given order.
+fn into_sorted_accounts(transaction_manager: TransactionManager, output_order: OutputOrder) -> Vec<ClientAccount> {
+    let mut client_accounts: Vec<ClientAccount> = transaction_manager.client_account_index
+        .into_values()
+        .collect();
+    sort_accounts(&mut client_accounts, output_order);
+    client_accounts
+}
+
+/// Sort the client accounts in the given order, to always return them in the same order.
+///
+/// This is the only ordering of the client accounts, so it is the same for all the output formats.
+/// Ties are broken by client id.
+fn sort_accounts<A: Borrow<ClientAccount>>(client_accounts: &mut [A], output_order: OutputOrder) {
+    match output_order {
+        OutputOrder::ClientIdAscending => client_accounts.sort_by_key(|client_account| client_account.borrow().client),
+        OutputOrder::FirstSeen => client_accounts.sort_by_key(|client_account| {
+            let client_account = client_account.borrow();
+            (client_account.first_seen, client_account.client)
+        }),
+    }
+}
+
+/// Keep only the client accounts of the clients of the filter, if any.
+fn filter_accounts(client_accounts: &mut Vec<&ClientAccount>, client_filter: Option<&HashSet<ClientId>>) {
+    if let Some(client_filter) = client_filter {
+        client_accounts.retain(|client_account| client_filter.contains(&client_account.client));
+    }
+}
+
+/// Write the client accounts to the writer in the given format.
+fn write_accounts<W: io::Write>(
+    client_accounts: &[&ClientAccount],
+    writer: W,
+    output_format: OutputFormat,
+    writer_options: WriterOptions,
+) -> Result<W, EngineError> {
+    match output_format {
+        OutputFormat::Csv => {
+            let mut csv_writer = csv_writer::CsvWriter::new(writer, writer_options);
+            for client_account in client_accounts {
+                csv_writer.write(client_account)?;
+            }
+            csv_writer.into_inner()
+        }
+        OutputFormat::Json => {
+            // A totals row would change the type of the elements of the JSON array.
+            if writer_options.include_totals {
+                return Err(EngineError::Config(String::from("The totals row isn't supported by the JSON output format")));
+            }
+            let mut json_writer = json_writer::JsonWriter::new(writer, writer_options);
+            for client_account in client_accounts {
+                json_writer.write(client_account)?;
+            }
+            json_writer.into_inner()
+        }
+        OutputFormat::Table => {
+            let mut table_writer = table_writer::TableWriter::new(writer, writer_options);
+            for client_account in client_accounts {
+                table_writer.write(client_account)?;
+            }
+            table_writer.into_inner()
+        }
     }
+}
 
-    Ok(csv_writer.into_inner().unwrap())
+/// Write the client accounts to a buffer, read them back to check them with [`self_check_written`],
+/// and only then write them to the writer, so that inconsistent client accounts are never written.
+fn write_checked_accounts<W: io::Write>(
+    client_accounts: &[&ClientAccount],
+    mut writer: W,
+    output_format: OutputFormat,
+    writer_options: WriterOptions,
+) -> Result<W, EngineError> {
+    let output = write_accounts(client_accounts, Vec::new(), output_format, writer_options.clone())?;
+    let written = output_reader::read_accounts(&output, output_format, &writer_options)
+        .map_err(|error| EngineError::Inconsistent(vec![ConsistencyError::Unreadable(error)]))?;
+    self_check_written(&written).map_err(EngineError::Inconsistent)?;
+    writer.write_all(&output)?;
+    writer.flush()?;
+    Ok(writer)
 }
 
 #[cfg(test)]
 mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
     use super::*;
 
+    /// Default config reading from the given reader and writing to a vector.
+    fn config<R: io::Read>(reader: R) -> Config<R, Vec<u8>> {
+        ConfigBuilder::new().reader(reader).writer(vec![]).build()
+    }
+
+    fn config_with<R: io::Read>(reader: R, options: Options) -> Config<R, Vec<u8>> {
+        Config { options, ..config(reader) }
+    }
+
     #[test]
     fn process_transactions() {
         let reader =
@@ -104,27 +1262,971 @@ mod tests {
             withdrawal,1,4,1.0\n\
             dispute,1,3,\n\
             chargeback,1,3,".as_bytes();
-        let writer = run(Config { reader, writer: vec![] }).unwrap();
-        assert_eq!("client,available,held,total,locked\n1,-1.0,0.0,-1.0,true\n", std::str::from_utf8(&writer).unwrap());
+        let writer = run(config(reader)).unwrap();
+        assert_eq!("client,available,held,total,locked\n1,-1.0000,0.0000,-1.0000,true\n", std::str::from_utf8(&writer).unwrap());
     }
 
     #[test]
-    fn process_transactions_with_spaces() {
+    fn process_transactions_to_json() {
         let reader =
-            "type, client, tx, amount\n\
-             deposit, 1, 1, 1.0\n\
-             withdrawal,  1,  2, 1.0\n".as_bytes();
-        let writer = run(Config { reader, writer: vec![] }).unwrap();
-        assert_eq!("client,available,held,total,locked\n1,0.0,0.0,0.0,false\n", std::str::from_utf8(&writer).unwrap());
+            "type,client,tx,amount\n\
+            deposit,1,1,1.9999\n\
+            withdrawal,1,2,0.1111\n\
+            deposit,2,3,2.0\n".as_bytes();
+        let writer = run(config_with(reader, Options { output_format: OutputFormat::Json, ..Options::default() })).unwrap();
+        assert_eq!(
+            "[{\"client\":1,\"available\":\"1.8888\",\"held\":\"0.0000\",\"total\":\"1.8888\",\"locked\":false},\
+            {\"client\":2,\"available\":\"2.0000\",\"held\":\"0.0000\",\"total\":\"2.0000\",\"locked\":false}]",
+            std::str::from_utf8(&writer).unwrap()
+        );
     }
 
     #[test]
-    fn process_transactions_with_4_decimals() {
+    fn round_amounts_in_output() {
         let reader =
             "type,client,tx,amount\n\
-            deposit,1,1,1.9999\n\
-            withdrawal,1,2,0.1111\n".as_bytes();
-        let writer = run(Config { reader, writer: vec![] }).unwrap();
-        assert_eq!("client,available,held,total,locked\n1,1.8888,0.0,1.8888,false\n", std::str::from_utf8(&writer).unwrap());
+            deposit,1,1,1.99995\n\
+            deposit,2,2,2.00005\n".as_bytes();
+        let expected = [
+            (RoundingMode::Truncate, "1,1.9999,0.0000,1.9999,false\n2,2.0000,0.0000,2.0000,false\n"),
+            (RoundingMode::HalfUp, "1,2.0000,0.0000,2.0000,false\n2,2.0001,0.0000,2.0001,false\n"),
+            (RoundingMode::HalfEven, "1,2.0000,0.0000,2.0000,false\n2,2.0000,0.0000,2.0000,false\n"),
+        ];
+        for (rounding_mode, accounts) in expected {
+            let writer = run(config_with(reader, Options { rounding_mode, ..Options::default() })).unwrap();
+            assert_eq!(format!("client,available,held,total,locked\n{}", accounts), std::str::from_utf8(&writer).unwrap());
+        }
+        let writer = run(config_with(reader, Options { rounding_mode: RoundingMode::HalfEven, output_format: OutputFormat::Json, ..Options::default() })).unwrap();
+        assert!(std::str::from_utf8(&writer).unwrap().starts_with("[{\"client\":1,\"available\":\"2.0000\""));
+    }
+
+    #[test]
+    fn callback_on_each_transaction() {
+        let reader =
+            "type,client,tx,amount\n\
+            deposit,1,1,2.0\n\
+            withdrawal,1,2,5.0\n\
+            dispute,1,1,\n\
+            chargeback,1,1,\n\
+            deposit,1,3,1.0\n".as_bytes();
+        let tallies = Rc::new(RefCell::new(HashMap::new()));
+        let on_transaction = {
+            let tallies = Rc::clone(&tallies);
+            move |_: &Transaction, outcome: &ProcessedOutcome| *tallies.borrow_mut().entry(*outcome).or_insert(0) += 1
+        };
+        let config = config_with(reader, Options { on_transaction: Some(Box::new(on_transaction)), ..Options::default() });
+        let writer = run(config).unwrap();
+        assert_eq!("client,available,held,total,locked\n1,0.0000,0.0000,0.0000,true\n", std::str::from_utf8(&writer).unwrap());
+        assert_eq!(*RefCell::borrow(&tallies), HashMap::from([
+            (ProcessedOutcome::Applied, 2),
+            (ProcessedOutcome::Locked, 1),
+            (ProcessedOutcome::Ignored(IgnoreReason::InsufficientFunds), 1),
+            (ProcessedOutcome::Ignored(IgnoreReason::AccountLocked), 1),
+        ]));
+    }
+
+    /// Writer into a buffer shared with the test.
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl io::Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn process_transactions_with_journal() {
+        let reader =
+            "type,client,tx,amount\n\
+            deposit,1,1,2.0\n\
+            withdrawal,1,2,5.0\n\
+            dispute,1,1,\n".as_bytes();
+        let journal = Rc::new(RefCell::new(Vec::new()));
+        let config = config_with(reader, Options { journal_writer: Some(Box::new(SharedBuffer(Rc::clone(&journal)))), ..Options::default() });
+        let writer = run(config).unwrap();
+        assert_eq!("client,available,held,total,locked\n1,0.0000,2.0000,2.0000,false\n", std::str::from_utf8(&writer).unwrap());
+        assert_eq!(
+            "{\"tx\":1,\"client\":1,\"type\":\"deposit\",\"available_before\":\"0.0\",\"available_after\":\"2.0\",\"held_before\":\"0.0\",\"held_after\":\"0.0\"}\n\
+            {\"tx\":1,\"client\":1,\"type\":\"dispute\",\"available_before\":\"2.0\",\"available_after\":\"0.0\",\"held_before\":\"0.0\",\"held_after\":\"2.0\"}\n",
+            std::str::from_utf8(&RefCell::borrow(&journal)).unwrap(),
+        );
+    }
+
+    #[test]
+    fn process_transactions_without_headers() {
+        let reader = include_str!("../sample.csv").split_once('\n').unwrap().1.as_bytes();
+        let writer = run(config_with(reader, Options { has_headers: false, ..Options::default() })).unwrap();
+        assert_eq!("client,available,held,total,locked\n1,-1.0000,0.0000,-1.0000,true\n", std::str::from_utf8(&writer).unwrap());
+        let writer = run(config("deposit,1,1,1.0\ndeposit,2,2,1.0\n".as_bytes()));
+        assert!(writer.is_err());
+    }
+
+    #[test]
+    fn process_max_transactions() {
+        let reader = include_str!("../sample.csv").as_bytes();
+        let writer = run(config_with(reader, Options { max_transactions: Some(2), ..Options::default() })).unwrap();
+        assert_eq!("client,available,held,total,locked\n1,0.0000,0.0000,0.0000,false\n", std::str::from_utf8(&writer).unwrap());
+        let writer = run_parallel(config_with(reader, Options { max_transactions: Some(2), ..Options::default() }), 2).unwrap();
+        assert_eq!("client,available,held,total,locked\n1,0.0000,0.0000,0.0000,false\n", std::str::from_utf8(&writer).unwrap());
+    }
+
+    #[test]
+    fn process_oversized_field_fails() {
+        let input = format!("type,client,tx,amount\ndeposit,1,1,{}\n", "1".repeat(1024));
+        let result = run(config_with(input.as_bytes(), Options { max_field_len: 1000, ..Options::default() }));
+        assert!(matches!(result, Err(EngineError::CsvParse { line: 2, record: None, .. })));
+    }
+
+    #[test]
+    fn process_without_disputes_expected() {
+        let input =
+            "type,client,tx,amount\n\
+            deposit,1,1,2.0\n\
+            withdrawal,1,2,0.5\n\
+            dispute,1,1,\n";
+        let writer = run(config_with(input.as_bytes(), Options { disputes_expected: false, ..Options::default() })).unwrap();
+        assert_eq!("client,available,held,total,locked\n1,1.5000,0.0000,1.5000,false\n", std::str::from_utf8(&writer).unwrap());
+        let writer = run_parallel(config_with(input.as_bytes(), Options { disputes_expected: false, ..Options::default() }), 2).unwrap();
+        assert_eq!("client,available,held,total,locked\n1,1.5000,0.0000,1.5000,false\n", std::str::from_utf8(&writer).unwrap());
+    }
+
+    #[test]
+    fn process_with_empty_amount_policy() {
+        let input = "type,client,tx,amount\ndeposit,1,1,\ndeposit,1,2,1.0\n";
+        let result = run(config(input.as_bytes()));
+        assert!(matches!(result, Err(EngineError::CsvParse { line: 2, .. })));
+        for empty_amount_policy in [EmptyAmountPolicy::TreatAsZero, EmptyAmountPolicy::Skip] {
+            let writer = run(config_with(input.as_bytes(), Options { empty_amount_policy, ..Options::default() })).unwrap();
+            assert_eq!("client,available,held,total,locked\n1,1.0000,0.0000,1.0000,false\n", std::str::from_utf8(&writer).unwrap());
+        }
+    }
+
+    #[test]
+    fn process_with_trim() {
+        let input = "type,client,tx,amount\n deposit,1,1,1.0\n";
+        let writer = run(config(input.as_bytes())).unwrap();
+        assert_eq!("client,available,held,total,locked\n1,1.0000,0.0000,1.0000,false\n", std::str::from_utf8(&writer).unwrap());
+        let err = run(config_with(input.as_bytes(), Options { trim: Trim::None, ..Options::default() })).unwrap_err();
+        assert!(matches!(err, EngineError::CsvParse { line: 2, .. }));
+    }
+
+    #[test]
+    fn process_with_overdraw_policy() {
+        let input =
+            "type,client,tx,amount\n\
+            deposit,1,1,2.0\n\
+            withdrawal,1,2,0.5\n\
+            withdrawal,1,3,2.0\n\
+            deposit,1,4,0.5\n";
+        let writer = run(config(input.as_bytes())).unwrap();
+        assert_eq!("client,available,held,total,locked\n1,2.0000,0.0000,2.0000,false\n", std::str::from_utf8(&writer).unwrap());
+        let err = run(config_with(input.as_bytes(), Options { overdraw_policy: OverdrawPolicy::Error, ..Options::default() })).unwrap_err();
+        assert!(matches!(err, EngineError::Overdraw { client: 1, tx: 3 }));
+        assert_eq!(err.to_string(), "The withdrawal 3 exceeds the available funds of the client account 1");
+        let err = run_parallel(config_with(input.as_bytes(), Options { overdraw_policy: OverdrawPolicy::Error, ..Options::default() }), 2).unwrap_err();
+        assert!(matches!(err, EngineError::Overdraw { client: 1, tx: 3 }));
+    }
+
+    #[test]
+    fn process_with_max_clients() {
+        let input =
+            "type,client,tx,amount\n\
+            deposit,1,1,2.0\n\
+            deposit,2,2,1.0\n\
+            withdrawal,2,3,0.5\n\
+            deposit,1,4,0.5\n";
+        let writer = run(config_with(input.as_bytes(), Options { max_clients: Some(1), ..Options::default() })).unwrap();
+        assert_eq!("client,available,held,total,locked\n1,2.5000,0.0000,2.5000,false\n", std::str::from_utf8(&writer).unwrap());
+        let result = run_parallel(config_with(input.as_bytes(), Options { max_clients: Some(1), ..Options::default() }), 2);
+        assert!(matches!(result, Err(EngineError::Config(_))));
+    }
+
+    #[test]
+    fn process_with_dispute_window() {
+        let input =
+            "type,client,tx,amount\n\
+            deposit,1,1,2.0\n\
+            deposit,2,2,1.0\n\
+            deposit,1,3,0.5\n\
+            dispute,2,2,\n\
+            dispute,1,1,\n";
+        let writer = run(config_with(input.as_bytes(), Options { dispute_window: Some(2), ..Options::default() })).unwrap();
+        assert_eq!("client,available,held,total,locked\n1,2.5000,0.0000,2.5000,false\n2,0.0000,1.0000,1.0000,false\n", std::str::from_utf8(&writer).unwrap());
+        let writer = run(config_with(input.as_bytes(), Options { dispute_window: Some(4), ..Options::default() })).unwrap();
+        assert_eq!("client,available,held,total,locked\n1,0.5000,2.0000,2.5000,false\n2,0.0000,1.0000,1.0000,false\n", std::str::from_utf8(&writer).unwrap());
+        let result = run_parallel(config_with(input.as_bytes(), Options { dispute_window: Some(2), ..Options::default() }), 2);
+        assert!(matches!(result, Err(EngineError::Config(_))));
+    }
+
+    #[test]
+    fn process_frozen_account() {
+        let reader =
+            "type,client,tx,amount\n\
+            deposit,1,1,1.0\n\
+            freeze,1,2,\n\
+            deposit,1,3,1.0\n\
+            thaw,1,4,\n\
+            deposit,1,5,2.0\n".as_bytes();
+        let writer = run(config(reader)).unwrap();
+        assert_eq!("client,available,held,total,locked\n1,3.0000,0.0000,3.0000,false\n", std::str::from_utf8(&writer).unwrap());
+    }
+
+    #[test]
+    fn process_comma_decimal_amounts() {
+        let reader = "type;client;tx;amount\ndeposit;1;1;1,50\n".as_bytes();
+        let writer = run(config_with(reader, Options { amount_format: AmountFormat::CommaDecimal, ..Options::default() })).unwrap();
+        assert_eq!("client;available;held;total;locked\n1;1.5000;0.0000;1.5000;false\n", std::str::from_utf8(&writer).unwrap());
+    }
+
+    #[test]
+    fn process_no_transactions_to_json() {
+        let reader = "type,client,tx,amount\n".as_bytes();
+        let writer = run(config_with(reader, Options { output_format: OutputFormat::Json, ..Options::default() })).unwrap();
+        assert_eq!("[]", std::str::from_utf8(&writer).unwrap());
+    }
+
+    #[test]
+    fn process_transactions_verbose() {
+        let reader =
+            "type,client,tx,amount\n\
+            deposit,1,1,1.0\n\
+            withdrawal,1,2,2.0\n".as_bytes();
+        let writer = run(config_with(reader, Options { verbose: true, ..Options::default() })).unwrap();
+        assert_eq!("client,available,held,total,locked\n1,1.0000,0.0000,1.0000,false\n", std::str::from_utf8(&writer).unwrap());
+    }
+
+    #[test]
+    fn summarize_processing() {
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.process_transaction(Transaction::deposit(1, 1, "1.0".parse().unwrap())).unwrap();
+        transaction_manager.process_transaction(Transaction::withdrawal(1, 2, "2.0".parse().unwrap())).unwrap();
+        transaction_manager.process_transaction(Transaction::deposit(2, 3, "1.0".parse().unwrap())).unwrap();
+        assert_eq!(transaction_manager.processed(), 3);
+        assert_eq!(summary(&[transaction_manager]), "processed 3 transactions for 2 clients, 1 ignored, 0.0 held, 0 disputes opened, 0 resolved, 0 charged back");
+    }
+
+    #[test]
+    fn format_counts() {
+        assert_eq!(format_count(0), "0");
+        assert_eq!(format_count(999), "999");
+        assert_eq!(format_count(1000), "1,000");
+        assert_eq!(format_count(1234567), "1,234,567");
+    }
+
+    #[test]
+    fn process_transactions_from_boxed_reader() {
+        let reader: Box<dyn io::Read> = Box::new(io::Cursor::new("type,client,tx,amount\ndeposit,1,1,1.0\n"));
+        let writer = run(config(reader)).unwrap();
+        assert_eq!("client,available,held,total,locked\n1,1.0000,0.0000,1.0000,false\n", std::str::from_utf8(&writer).unwrap());
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn process_gzipped_transactions() {
+        use std::io::Write;
+        let input =
+            "type,client,tx,amount\n\
+            deposit,1,1,1.0\n\
+            withdrawal,1,2,1.0\n\
+            dispute,1,1,\n\
+            resolve,1,1,\n\
+            deposit,1,3,1.0\n\
+            withdrawal,1,4,1.0\n\
+            dispute,1,3,\n\
+            chargeback,1,3,";
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(input.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let plain = run(config(input.as_bytes())).unwrap();
+        let writer = run(config(flate2::read::GzDecoder::new(compressed.as_slice()))).unwrap();
+        assert_eq!(std::str::from_utf8(&plain).unwrap(), std::str::from_utf8(&writer).unwrap());
+    }
+
+    #[test]
+    fn config_with_missing_file_fails() {
+        let args = vec![String::from("payments-engine-rs"), String::from("missing.csv")];
+        let result = Config::from_args(args.into_iter());
+        assert!(matches!(result, Err(EngineError::Io(_))));
+    }
+
+    #[test]
+    fn process_transactions_from_many_readers() {
+        let first_reader =
+            "type,client,tx,amount\n\
+            deposit,1,1,1.0\n\
+            withdrawal,1,2,1.0\n\
+            dispute,1,1,\n\
+            resolve,1,1,\n".as_bytes();
+        let second_reader =
+            "type,client,tx,amount\n\
+            deposit,1,3,1.0\n\
+            withdrawal,1,4,1.0\n\
+            dispute,1,3,\n\
+            chargeback,1,3,".as_bytes();
+        let writer = run_many(vec![first_reader, second_reader], vec![]).unwrap();
+        assert_eq!("client,available,held,total,locked\n1,-1.0000,0.0000,-1.0000,true\n", std::str::from_utf8(&writer).unwrap());
+    }
+
+    #[test]
+    fn dispute_transaction_from_previous_reader() {
+        let first_reader = "type,client,tx,amount\ndeposit,1,1,1.0\n".as_bytes();
+        let second_reader = "type,client,tx,amount\ndispute,1,1,\n".as_bytes();
+        let writer = run_many(vec![first_reader, second_reader], vec![]).unwrap();
+        assert_eq!("client,available,held,total,locked\n1,0.0000,1.0000,1.0000,false\n", std::str::from_utf8(&writer).unwrap());
+    }
+
+    #[test]
+    fn process_transactions_in_parallel() {
+        let input =
+            "type,client,tx,amount\n\
+            deposit,3,1,3.0\n\
+            deposit,1,2,1.0\n\
+            withdrawal,3,3,1.5\n\
+            deposit,2,4,2.0\n\
+            dispute,1,2,\n\
+            deposit,4,5,4.0\n\
+            withdrawal,2,6,5.0\n\
+            dispute,3,3,\n\
+            chargeback,1,2,\n\
+            deposit,1,7,1.0\n\
+            resolve,3,3,\n\
+            withdrawal,4,8,0.5\n\
+            deposit,2,5,9.0\n";
+        for output_order in [OutputOrder::ClientIdAscending, OutputOrder::FirstSeen] {
+            let serial = run(config_with(input.as_bytes(), Options { output_order, ..Options::default() })).unwrap();
+            for num_threads in 1..=4 {
+                let parallel = run_parallel(config_with(input.as_bytes(), Options { output_order, ..Options::default() }), num_threads).unwrap();
+                assert_eq!(std::str::from_utf8(&serial).unwrap(), std::str::from_utf8(&parallel).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn process_transactions_skipping_bad_records() {
+        let reader =
+            "type,client,tx,amount\n\
+            deposit,1,1,1.0\n\
+            deposit,-1,2,1.0\n\
+            deposit,1,3,2.0\n".as_bytes();
+        assert!(matches!(run(config(reader)), Err(EngineError::CsvParse { line: 3, .. })));
+        let writer = run(config_with(reader, Options { error_policy: ErrorPolicy::SkipBad, ..Options::default() })).unwrap();
+        assert_eq!("client,available,held,total,locked\n1,3.0000,0.0000,3.0000,false\n", std::str::from_utf8(&writer).unwrap());
+    }
+
+    #[test]
+    fn process_transactions_with_spaces() {
+        let reader =
+            "type, client, tx, amount\n\
+             deposit, 1, 1, 1.0\n\
+             withdrawal,  1,  2, 1.0\n".as_bytes();
+        let writer = run(config(reader)).unwrap();
+        assert_eq!("client,available,held,total,locked\n1,0.0000,0.0000,0.0000,false\n", std::str::from_utf8(&writer).unwrap());
+    }
+
+    #[test]
+    fn process_transactions_with_zero_amounts() {
+        let reader =
+            "type,client,tx,amount\n\
+            deposit,1,1,0.0\n\
+            deposit,2,2,1.0\n\
+            withdrawal,2,3,0\n".as_bytes();
+        let writer = run(config(reader)).unwrap();
+        assert_eq!("client,available,held,total,locked\n2,1.0000,0.0000,1.0000,false\n", std::str::from_utf8(&writer).unwrap());
+    }
+
+    #[test]
+    fn process_tab_separated_transactions() {
+        let reader =
+            "type\tclient\ttx\tamount\n\
+            deposit\t1\t1\t2.0\n\
+            withdrawal\t1\t2\t0.5\n\
+            deposit\t2\t3\t1.0\n\
+            dispute\t2\t3\t\n".as_bytes();
+        let writer = run(config_with(reader, Options { delimiter: b'\t', ..Options::default() })).unwrap();
+        assert_eq!("client\tavailable\theld\ttotal\tlocked\n\
+            1\t1.5000\t0.0000\t1.5000\tfalse\n\
+            2\t0.0000\t1.0000\t1.0000\tfalse\n", std::str::from_utf8(&writer).unwrap());
+    }
+
+    #[test]
+    fn validate_transactions() {
+        let reader =
+            "type,client,tx,amount\n\
+            deposit,1,1,2.0\n\
+            deposit,1,a,1.0\n\
+            withdrawal,1,2,5.0\n\
+            dispute,1,9,\n\
+            withdrawal,1,3,\n\
+            dispute,1,1,\n\
+            resolve,1,1,\n".as_bytes();
+        let report = validate(reader).unwrap();
+        assert_eq!(report.valid, 3);
+        assert_eq!(report.skipped.iter().map(|skipped| skipped.line).collect::<Vec<_>>(), [3, 6]);
+        assert_eq!(report.ignored, [
+            IgnoredTransaction { tx: 2, client: 1, reason: IgnoreReason::InsufficientFunds },
+            IgnoredTransaction { tx: 9, client: 1, reason: IgnoreReason::UnknownTransaction },
+        ]);
+    }
+
+    #[test]
+    fn write_newline_terminated_csv() {
+        let writer = run(config(include_str!("../sample.csv").as_bytes())).unwrap();
+        assert!(!writer.contains(&b'\r'));
+        assert!(!writer.starts_with(b"\xEF\xBB\xBF"));
+        assert!(writer.ends_with(b"\n"));
+    }
+
+    #[test]
+    fn process_transactions_with_4_decimals() {
+        let reader =
+            "type,client,tx,amount\n\
+            deposit,1,1,1.9999\n\
+            withdrawal,1,2,0.1111\n".as_bytes();
+        let writer = run(config(reader)).unwrap();
+        assert_eq!("client,available,held,total,locked\n1,1.8888,0.0000,1.8888,false\n", std::str::from_utf8(&writer).unwrap());
+    }
+
+    #[test]
+    fn process_transactions_with_2_decimals() {
+        let reader =
+            "type,client,tx,amount\n\
+            deposit,1,1,1.123456789\n\
+            deposit,2,2,0.009\n\
+            withdrawal,1,3,0.111\n".as_bytes();
+        let writer = run(config_with(reader, Options { precision: 2, error_policy: ErrorPolicy::SkipBad, ..Options::default() })).unwrap();
+        assert_eq!("client,available,held,total,locked\n\
+            1,1.01,0.00,1.01,false\n", std::str::from_utf8(&writer).unwrap());
+    }
+
+    #[test]
+    fn process_withdrawal_of_exact_available_funds() {
+        let reader = "type,client,tx,amount\ndeposit,1,1,1.2345\nwithdrawal,1,2,1.2345\n".as_bytes();
+        let writer = run(config_with(reader, Options { precision: 4, output_format: OutputFormat::Json, ..Options::default() })).unwrap();
+        assert_eq!(r#"[{"client":1,"available":"0.0000","held":"0.0000","total":"0.0000","locked":false}]"#, std::str::from_utf8(&writer).unwrap());
+    }
+
+    #[test]
+    fn process_transactions_with_8_decimals() {
+        let reader =
+            "type,client,tx,amount\n\
+            deposit,1,1,1.123456789\n\
+            deposit,2,2,0.009\n\
+            withdrawal,1,3,0.111\n".as_bytes();
+        let writer = run(config_with(reader, Options { precision: 8, error_policy: ErrorPolicy::SkipBad, ..Options::default() })).unwrap();
+        assert_eq!("client,available,held,total,locked\n\
+            1,1.01245678,0.00000000,1.01245678,false\n\
+            2,0.00900000,0.00000000,0.00900000,false\n", std::str::from_utf8(&writer).unwrap());
+    }
+
+    #[test]
+    fn process_transactions_with_tx_count() {
+        let reader =
+            "type,client,tx,amount\n\
+            deposit,1,1,2.0\n\
+            withdrawal,1,2,1.0\n\
+            withdrawal,1,3,5.0\n\
+            dispute,1,1,\n\
+            deposit,2,4,2.0\n".as_bytes();
+        let writer = run(config_with(reader, Options { include_tx_count: true, ..Options::default() })).unwrap();
+        assert_eq!("client,available,held,total,locked,tx_count\n\
+            1,-1.0000,2.0000,1.0000,false,2\n\
+            2,2.0000,0.0000,2.0000,false,1\n", std::str::from_utf8(&writer).unwrap());
+        let writer = run(config_with(reader, Options { include_tx_count: true, output_format: OutputFormat::Json, ..Options::default() })).unwrap();
+        assert!(std::str::from_utf8(&writer).unwrap().contains("\"locked\":false,\"tx_count\":2}"));
+    }
+
+    #[test]
+    fn process_transactions_with_locked_by() {
+        let reader =
+            "type,client,tx,amount\n\
+            deposit,1,1,2.0\n\
+            dispute,1,1,\n\
+            chargeback,1,1,\n\
+            deposit,2,2,2.0\n".as_bytes();
+        let writer = run(config_with(reader, Options { include_locked_by: true, ..Options::default() })).unwrap();
+        assert_eq!("client,available,held,total,locked,locked_by\n\
+            1,0.0000,0.0000,0.0000,true,1\n\
+            2,2.0000,0.0000,2.0000,false,\n", std::str::from_utf8(&writer).unwrap());
+        let writer = run(config_with(reader, Options { include_locked_by: true, output_format: OutputFormat::Json, ..Options::default() })).unwrap();
+        assert!(std::str::from_utf8(&writer).unwrap().contains("\"locked\":false,\"locked_by\":null}"));
+    }
+
+    #[test]
+    fn process_large_client_ids() {
+        let reader = "type,client,tx,amount\ndeposit,100000,1,1.5\ndeposit,4294967295,2,2.0\n".as_bytes();
+        let writer = run(config(reader)).unwrap();
+        assert_eq!("client,available,held,total,locked\n\
+            100000,1.5000,0.0000,1.5000,false\n\
+            4294967295,2.0000,0.0000,2.0000,false\n", std::str::from_utf8(&writer).unwrap());
+        let writer = run(config_with(reader, Options { client_filter: Some(parse_clients("100000").unwrap()), ..Options::default() })).unwrap();
+        assert_eq!("client,available,held,total,locked\n100000,1.5000,0.0000,1.5000,false\n", std::str::from_utf8(&writer).unwrap());
+    }
+
+    #[test]
+    fn process_skipping_unknown_types() {
+        let input = "type,client,tx,amount\nfoobar,1,1,5.0\ndeposit,1,2,1.0\n";
+        assert!(run(config(input.as_bytes())).is_err());
+        let writer = run(config_with(input.as_bytes(), Options { skip_unknown_types: true, ..Options::default() })).unwrap();
+        assert_eq!("client,available,held,total,locked\n1,1.0000,0.0000,1.0000,false\n", std::str::from_utf8(&writer).unwrap());
+    }
+
+    #[test]
+    fn process_resumed_at_offset() {
+        let input =
+            "# Transactions\n\
+            type,client,tx,amount\n\
+            deposit,1,1,1.0\n\
+            deposit,1,2,2.0\n\
+            withdrawal,1,3,0.5\n";
+        let resume_at = input.find("deposit,1,2").unwrap() as u64;
+        let writer = run_seekable(config_with(io::Cursor::new(input), Options { resume_at: Some(resume_at), comment_char: Some(b'#'), ..Options::default() })).unwrap();
+        assert_eq!("client,available,held,total,locked\n1,1.5000,0.0000,1.5000,false\n", std::str::from_utf8(&writer).unwrap());
+
+        let writer = run_seekable(config_with(io::Cursor::new(input), Options { resume_at: Some(resume_at), has_headers: false, ..Options::default() })).unwrap();
+        assert_eq!("client,available,held,total,locked\n1,1.5000,0.0000,1.5000,false\n", std::str::from_utf8(&writer).unwrap());
+
+        // The whole input is read from an offset within the header.
+        let writer = run_seekable(config_with(io::Cursor::new(input), Options { resume_at: Some(4), comment_char: Some(b'#'), ..Options::default() })).unwrap();
+        assert_eq!("client,available,held,total,locked\n1,2.5000,0.0000,2.5000,false\n", std::str::from_utf8(&writer).unwrap());
+
+        assert!(matches!(run(config_with(input.as_bytes(), Options { resume_at: Some(resume_at), ..Options::default() })), Err(EngineError::Config(_))));
+    }
+
+    #[test]
+    fn process_empty_input() {
+        let writer = run(config("".as_bytes())).unwrap();
+        assert_eq!("client,available,held,total,locked\n", std::str::from_utf8(&writer).unwrap());
+        let writer = run(config("type,client,tx,amount\n".as_bytes())).unwrap();
+        assert_eq!("client,available,held,total,locked\n", std::str::from_utf8(&writer).unwrap());
+        let writer = run(config_with("".as_bytes(), Options { has_headers: false, ..Options::default() })).unwrap();
+        assert_eq!("client,available,held,total,locked\n", std::str::from_utf8(&writer).unwrap());
+        let writer = run(config_with("".as_bytes(), Options { include_tx_count: true, include_last_tx: true, ..Options::default() })).unwrap();
+        assert_eq!("client,available,held,total,locked,tx_count,last_tx\n", std::str::from_utf8(&writer).unwrap());
+        let writer = run(config_with("".as_bytes(), Options { output_format: OutputFormat::Json, ..Options::default() })).unwrap();
+        assert_eq!("[]", std::str::from_utf8(&writer).unwrap());
+    }
+
+    #[test]
+    fn process_transactions_with_last_tx() {
+        let reader =
+            "type,client,tx,amount\n\
+            deposit,1,2,2.0\n\
+            deposit,1,1,1.0\n\
+            dispute,1,2,\n\
+            withdrawal,1,3,5.0\n\
+            deposit,2,4,2.0\n".as_bytes();
+        let writer = run(config_with(reader, Options { include_last_tx: true, ..Options::default() })).unwrap();
+        assert_eq!("client,available,held,total,locked,last_tx\n\
+            1,1.0000,2.0000,3.0000,false,2\n\
+            2,2.0000,0.0000,2.0000,false,4\n", std::str::from_utf8(&writer).unwrap());
+        let writer = run(config_with(reader, Options { include_last_tx: true, output_format: OutputFormat::Json, ..Options::default() })).unwrap();
+        assert!(std::str::from_utf8(&writer).unwrap().contains("\"locked\":false,\"last_tx\":4}"));
+    }
+
+    #[test]
+    fn process_transactions_filtered_by_client() {
+        let input =
+            "type,client,tx,amount\n\
+            deposit,1,1,1.0\n\
+            deposit,2,2,2.0\n\
+            deposit,3,3,3.0\n\
+            withdrawal,2,4,0.5\n";
+        let client_filter = Some(HashSet::from([2]));
+        let expected = "client,available,held,total,locked\n2,1.5000,0.0000,1.5000,false\n";
+        let writer = run(config_with(input.as_bytes(), Options { client_filter: client_filter.clone(), ..Options::default() })).unwrap();
+        assert_eq!(expected, std::str::from_utf8(&writer).unwrap());
+        let writer = run_parallel(config_with(input.as_bytes(), Options { client_filter, ..Options::default() }), 2).unwrap();
+        assert_eq!(expected, std::str::from_utf8(&writer).unwrap());
+    }
+
+    #[test]
+    fn process_transactions_of_reserved_clients() {
+        let input =
+            "type,client,tx,amount\n\
+            deposit,0,1,5.0\n\
+            deposit,1,2,1.0\n";
+        let writer = run(config_with(input.as_bytes(), Options { reserved_client_ids: HashSet::from([0]), ..Options::default() })).unwrap();
+        assert_eq!("client,available,held,total,locked\n1,1.0000,0.0000,1.0000,false\n", std::str::from_utf8(&writer).unwrap());
+        let writer = run_parallel(config_with(input.as_bytes(), Options { reserved_client_ids: HashSet::from([0]), ..Options::default() }), 2).unwrap();
+        assert_eq!("client,available,held,total,locked\n1,1.0000,0.0000,1.0000,false\n", std::str::from_utf8(&writer).unwrap());
+    }
+
+    #[cfg(feature = "checksum")]
+    #[test]
+    fn process_transactions_with_checksum() {
+        let (writer, checksum) = run_with_checksum(config(include_str!("../sample.csv").as_bytes())).unwrap();
+        assert_eq!("client,available,held,total,locked\n1,-1.0000,0.0000,-1.0000,true\n", std::str::from_utf8(&writer).unwrap());
+        let checksum: String = checksum.iter().map(|byte| format!("{:02x}", byte)).collect();
+        assert_eq!(checksum, "c4fd424746516e9f3e765d7fd7ac5cdcb75592068285f1948386920c52648df1");
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn process_transactions_from_stream() {
+        let transactions = vec![
+            Ok(Transaction::deposit(1, 1, "2.0".parse().unwrap())),
+            Ok(Transaction::withdrawal(1, 2, "0.5".parse().unwrap())),
+            Ok(Transaction::deposit(2, 3, "1.0".parse().unwrap())),
+            Ok(Transaction::dispute(2, 3)),
+        ];
+        let writer = run_async(futures::stream::iter(transactions), vec![]).await.unwrap();
+        assert_eq!("client,available,held,total,locked\n\
+            1,1.5000,0.0000,1.5000,false\n\
+            2,0.0000,1.0000,1.0000,false\n", std::str::from_utf8(&writer).unwrap());
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn process_stream_error() {
+        let transactions = vec![
+            Ok(Transaction::deposit(1, 1, "2.0".parse().unwrap())),
+            Err(EngineError::Io(io::Error::other("connection reset"))),
+        ];
+        let result = run_async(futures::stream::iter(transactions), vec![]).await;
+        assert!(matches!(result, Err(EngineError::Io(_))));
+    }
+
+    #[test]
+    fn config_with_client_filter() {
+        let args = ["payments-engine-rs", "--clients", "1, 2"].map(String::from);
+        let config = Config::from_args(args.into_iter()).unwrap();
+        assert_eq!(config.options.client_filter, Some(HashSet::from([1, 2])));
+        let args = ["payments-engine-rs", "--clients", "1,a"].map(String::from);
+        assert!(matches!(Config::from_args(args.into_iter()), Err(EngineError::Config(_))));
+        let args = ["payments-engine-rs", "--clients"].map(String::from);
+        assert!(matches!(Config::from_args(args.into_iter()), Err(EngineError::Config(_))));
+    }
+
+    #[test]
+    fn config_with_unknown_option_fails() {
+        let args = ["payments-engine-rs", "--client", "1", "sample.csv"].map(String::from);
+        let result = Config::from_args(args.into_iter());
+        assert!(matches!(result, Err(EngineError::Config(message)) if message == "Unknown option --client"));
+        let args = ["payments-engine-rs", "sample.csv", "-x"].map(String::from);
+        assert!(matches!(Config::from_args(args.into_iter()), Err(EngineError::Config(_))));
+    }
+
+    #[test]
+    fn config_with_many_files_fails() {
+        let args = ["payments-engine-rs", "sample.csv", "other.csv"].map(String::from);
+        let result = Config::from_args(args.into_iter());
+        assert!(matches!(result, Err(EngineError::Config(message)) if message == "Unexpected argument other.csv"));
+    }
+
+    #[test]
+    fn config_with_round_trip() {
+        let args = ["payments-engine-rs", "sample.csv"].map(String::from);
+        assert!(!Config::from_args(args.into_iter()).unwrap().options.self_check);
+        let args = ["payments-engine-rs", "--round-trip", "sample.csv"].map(String::from);
+        assert!(Config::from_args(args.into_iter()).unwrap().options.self_check);
+    }
+
+    #[test]
+    fn config_with_stats() {
+        let args = ["payments-engine-rs", "sample.csv"].map(String::from);
+        assert!(!Config::from_args(args.into_iter()).unwrap().options.verbose);
+        let args = ["payments-engine-rs", "sample.csv", "--stats"].map(String::from);
+        assert!(Config::from_args(args.into_iter()).unwrap().options.verbose);
+    }
+
+    #[test]
+    fn count_disputes_of_sample() {
+        let mut transaction_manager = TransactionManager::new();
+        let reader = include_str!("../sample.csv").as_bytes();
+        process_reader(&mut transaction_manager, reader, ReaderOptions::default(), None, None).unwrap();
+        assert_eq!(transaction_manager.dispute_stats(), DisputeStats { opened: 2, resolved: 1, charged_back: 1 });
+        assert!(summary(&[transaction_manager]).ends_with(", 2 disputes opened, 1 resolved, 1 charged back"));
+    }
+
+    #[test]
+    fn config_with_missing_output_fails() {
+        let args = ["payments-engine-rs", "sample.csv", "-o"].map(String::from);
+        assert!(matches!(Config::from_args(args.into_iter()), Err(EngineError::Config(_))));
+    }
+
+    #[test]
+    fn process_transactions_with_totals() {
+        let reader =
+            "type,client,tx,amount\n\
+            deposit,1,1,1.5\n\
+            deposit,2,2,2.0\n\
+            deposit,2,3,1.25\n\
+            dispute,2,2,\n\
+            deposit,1,4,2.0\n\
+            dispute,1,4,\n\
+            chargeback,1,4,\n";
+        let writer = run(config_with(reader.as_bytes(), Options { include_totals: true, ..Options::default() })).unwrap();
+        assert_eq!("client,available,held,total,locked\n\
+            1,1.5000,0.0000,1.5000,true\n\
+            2,1.2500,2.0000,3.2500,false\n\
+            TOTAL,2.7500,2.0000,4.7500,1\n", std::str::from_utf8(&writer).unwrap());
+        let writer = run_parallel(config_with(reader.as_bytes(), Options { include_totals: true, include_tx_count: true, ..Options::default() }), 2).unwrap();
+        assert_eq!("client,available,held,total,locked,tx_count\n\
+            1,1.5000,0.0000,1.5000,true,2\n\
+            2,1.2500,2.0000,3.2500,false,2\n\
+            TOTAL,2.7500,2.0000,4.7500,1,\n", std::str::from_utf8(&writer).unwrap());
+        let writer = run(config_with(reader.as_bytes(), Options { include_totals: true, output_format: OutputFormat::Table, ..Options::default() })).unwrap();
+        assert_eq!(std::str::from_utf8(&writer).unwrap().lines().nth(6).unwrap(), "| TOTAL  |    2.7500 | 2.0000 | 4.7500 |      1 |");
+
+        let writer = run(config_with("".as_bytes(), Options { include_totals: true, ..Options::default() })).unwrap();
+        assert_eq!("client,available,held,total,locked\nTOTAL,0.0000,0.0000,0.0000,0\n", std::str::from_utf8(&writer).unwrap());
+        let result = run(config_with(reader.as_bytes(), Options { include_totals: true, output_format: OutputFormat::Json, ..Options::default() }));
+        assert!(matches!(result, Err(EngineError::Config(_))));
+    }
+
+    #[test]
+    fn process_large_balances_with_totals() {
+        // Each client account is near the maximum amount, but their sums are out of its bounds.
+        let mut reader = String::from("type,client,tx,amount\n");
+        for client in 1..=1000 {
+            reader.push_str(&format!("deposit,{},{},90000000000.0\n", client, 2 * client));
+            reader.push_str(&format!("deposit,{},{},1000000.0\n", client, 2 * client + 1));
+            reader.push_str(&format!("dispute,{},{},\n", client, 2 * client + 1));
+        }
+        let writer = run(config_with(reader.as_bytes(), Options { include_totals: true, ..Options::default() })).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&writer).unwrap().lines().last().unwrap(),
+            "TOTAL,92233720368.5477,1000000000.0000,92233720368.5477,0",
+        );
+
+        // The sums of the amounts out of the bounds are exact, and back within them.
+        let reader =
+            "type,client,tx,amount\n\
+            deposit,1,1,90000000000.0\n\
+            deposit,2,2,90000000000.0\n\
+            credit_limit,3,3,90000000000.0\n\
+            withdrawal,3,4,90000000000.0\n";
+        let writer = run(config_with(reader.as_bytes(), Options { include_totals: true, ..Options::default() })).unwrap();
+        assert_eq!(std::str::from_utf8(&writer).unwrap().lines().last().unwrap(), "TOTAL,90000000000.0000,0.0000,90000000000.0000,0");
+    }
+
+    #[test]
+    fn process_transactions_to_table() {
+        let reader =
+            "type,client,tx,amount\n\
+            deposit,12,1,1234.5\n\
+            deposit,3,2,1.0\n\
+            dispute,3,2,\n\
+            chargeback,3,2,\n".as_bytes();
+        let writer = run(config_with(reader, Options { output_format: OutputFormat::Table, include_locked_by: true, ..Options::default() })).unwrap();
+        assert_eq!(
+            "+--------+-----------+--------+-----------+--------+-----------+\n\
+             | client | available | held   | total     | locked | locked_by |\n\
+             +--------+-----------+--------+-----------+--------+-----------+\n\
+             |      3 |    0.0000 | 0.0000 |    0.0000 | true   |         2 |\n\
+             |     12 | 1234.5000 | 0.0000 | 1234.5000 | false  |           |\n\
+             +--------+-----------+--------+-----------+--------+-----------+\n",
+            std::str::from_utf8(&writer).unwrap(),
+        );
+
+        // All the lines have the same width.
+        let writer = run(config_with(include_str!("../sample.csv").as_bytes(), Options { output_format: OutputFormat::Table, include_tx_count: true, ..Options::default() })).unwrap();
+        let table = std::str::from_utf8(&writer).unwrap();
+        assert!(table.lines().all(|line| line.len() == table.lines().next().unwrap().len()));
+        assert_eq!(table.lines().nth(1).unwrap(), "| client | available | held   | total   | locked | tx_count |");
+
+        let writer = run(config_with("".as_bytes(), Options { output_format: OutputFormat::Table, ..Options::default() })).unwrap();
+        assert_eq!(
+            "+--------+-----------+------+-------+--------+\n\
+             | client | available | held | total | locked |\n\
+             +--------+-----------+------+-------+--------+\n",
+            std::str::from_utf8(&writer).unwrap(),
+        );
+    }
+
+    #[test]
+    fn csv_and_json_share_the_order() {
+        let mut transaction_manager = TransactionManager::new();
+        for client in [5, 3, 9, 1, 7] {
+            transaction_manager.process_transaction(Transaction::deposit(client, client, "1.0".parse().unwrap())).unwrap();
+        }
+        for output_order in [OutputOrder::ClientIdAscending, OutputOrder::FirstSeen] {
+            let client_accounts = sorted_accounts(&transaction_manager, output_order);
+            let csv = write_accounts(&client_accounts, vec![], OutputFormat::Csv, WriterOptions::default()).unwrap();
+            let csv_clients: Vec<String> = std::str::from_utf8(&csv).unwrap().lines().skip(1)
+                .map(|line| line.split(',').next().unwrap().to_string())
+                .collect();
+            let json = write_accounts(&client_accounts, vec![], OutputFormat::Json, WriterOptions::default()).unwrap();
+            let json: Vec<serde_json::Value> = serde_json::from_slice(&json).unwrap();
+            let json_clients: Vec<String> = json.iter().map(|account| account["client"].to_string()).collect();
+            assert_eq!(csv_clients, json_clients);
+        }
+        let client_accounts = sorted_accounts(&transaction_manager, OutputOrder::FirstSeen);
+        let clients: Vec<ClientId> = client_accounts.iter().map(|client_account| client_account.client).collect();
+        assert_eq!(clients, [5, 3, 9, 1, 7]);
+    }
+
+    #[test]
+    fn process_transactions_sorted_by_client() {
+        let reader =
+            "type,client,tx,amount\n\
+            deposit,3,1,3.0\n\
+            deposit,1,2,1.0\n\
+            deposit,2,3,2.0\n".as_bytes();
+        let writer = run(config(reader)).unwrap();
+        assert_eq!("client,available,held,total,locked\n\
+            1,1.0000,0.0000,1.0000,false\n\
+            2,2.0000,0.0000,2.0000,false\n\
+            3,3.0000,0.0000,3.0000,false\n", std::str::from_utf8(&writer).unwrap());
+    }
+
+    #[test]
+    fn process_transactions_in_first_seen_order() {
+        let reader =
+            "type,client,tx,amount\n\
+            deposit,3,1,3.0\n\
+            deposit,1,2,1.0\n\
+            deposit,3,3,3.0\n\
+            deposit,2,4,2.0\n".as_bytes();
+        let writer = run(config_with(reader, Options { output_order: OutputOrder::FirstSeen, ..Options::default() })).unwrap();
+        assert_eq!("client,available,held,total,locked\n\
+            3,6.0000,0.0000,6.0000,false\n\
+            1,1.0000,0.0000,1.0000,false\n\
+            2,2.0000,0.0000,2.0000,false\n", std::str::from_utf8(&writer).unwrap());
+    }
+
+    #[test]
+    fn run_to_sorted_accounts() {
+        let reader =
+            "type,client,tx,amount\n\
+            deposit,2,1,2.0\n\
+            deposit,1,2,1.0\n\
+            dispute,1,2,\n".as_bytes();
+        let client_accounts = run_to_accounts(reader).unwrap().accounts;
+        assert_eq!(client_accounts.len(), 2);
+        assert_eq!(client_accounts[0].client, 1);
+        assert_eq!(client_accounts[0].available, Amount::ZERO);
+        assert_eq!(client_accounts[0].held, "1.0".parse().unwrap());
+        assert_eq!(client_accounts[1].client, 2);
+        assert_eq!(client_accounts[1].available, "2.0".parse().unwrap());
+        assert_eq!(client_accounts[1].held, Amount::ZERO);
+    }
+
+    #[test]
+    fn warn_about_negative_balances() {
+        let reader =
+            "type,client,tx,amount\n\
+            deposit,1,1,2.0\n\
+            withdrawal,1,2,1.5\n\
+            dispute,1,1,\n\
+            deposit,2,3,2.0\n\
+            withdrawal,2,4,2.0\n\
+            dispute,2,3,\n\
+            chargeback,2,3,\n\
+            deposit,3,5,1.0\n\
+            credit_limit,3,6,2.0\n\
+            withdrawal,3,7,2.5\n".as_bytes();
+        let report = run_to_accounts(reader).unwrap();
+        assert_eq!(report.accounts.len(), 3);
+        assert_eq!(report.warnings, [NegativeBalanceWarning { client: 1, available: "-1.5".parse().unwrap() }]);
+    }
+
+    #[test]
+    fn self_check_inconsistent_accounts() {
+        let mut overflowing = ClientAccount::new(1, Amount::MAX, "1.0".parse().unwrap());
+        overflowing.locked = true;
+        let negative_held = ClientAccount::new(2, "1.0".parse().unwrap(), "-0.5".parse().unwrap());
+        let mut locked_with_held = ClientAccount::new(3, Amount::ZERO, "2.0".parse().unwrap());
+        locked_with_held.locked = true;
+        let consistent = ClientAccount::new(4, "-1.0".parse().unwrap(), "1.0".parse().unwrap());
+        assert_eq!(self_check(&[overflowing, negative_held, locked_with_held, consistent]), Err(vec![
+            ConsistencyError::TotalOverflow { client: 1 },
+            ConsistencyError::LockedWithHeld { client: 1, held: "1.0".parse().unwrap() },
+            ConsistencyError::NegativeHeld { client: 2, held: "-0.5".parse().unwrap() },
+            ConsistencyError::LockedWithHeld { client: 3, held: "2.0".parse().unwrap() },
+        ]));
+        assert_eq!(self_check::<ClientAccount>(&[]), Ok(()));
+    }
+
+    #[test]
+    fn self_check_locked_with_other_dispute_open() {
+        // The chargeback of the first deposit locks the account while the second one is disputed.
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.apply_str("deposit,1,1,2.0").unwrap();
+        transaction_manager.apply_str("deposit,1,2,1.5").unwrap();
+        transaction_manager.apply_str("dispute,1,1,").unwrap();
+        transaction_manager.apply_str("dispute,1,2,").unwrap();
+        transaction_manager.apply_str("chargeback,1,1,").unwrap();
+        assert_eq!(transaction_manager.apply_str("resolve,1,2,").unwrap(), ProcessedOutcome::Ignored(IgnoreReason::AccountLocked));
+        assert_eq!(self_check(&transaction_manager.accounts().collect::<Vec<_>>()), Err(vec![
+            ConsistencyError::LockedWithHeld { client: 1, held: "1.5".parse().unwrap() },
+        ]));
+    }
+
+    #[test]
+    fn process_with_self_check() {
+        let input = "type,client,tx,amount\ndeposit,1,1,2.0\ndispute,1,1,\nchargeback,1,1,\ndeposit,2,2,1.0\n";
+        let writer = run(config_with(input.as_bytes(), Options { self_check: true, ..Options::default() })).unwrap();
+        assert_eq!("client,available,held,total,locked\n1,0.0000,0.0000,0.0000,true\n2,1.0000,0.0000,1.0000,false\n", std::str::from_utf8(&writer).unwrap());
+
+        // A partial chargeback locks the account with the remainder of the disputed funds held.
+        let input = "type,client,tx,amount\ndeposit,1,1,2.0\ndispute,1,1,\nchargeback,1,1,0.5\n";
+        let err = run(config_with(input.as_bytes(), Options { self_check: true, ..Options::default() })).unwrap_err();
+        assert_eq!(err.to_string(), "The client accounts are inconsistent: the locked client account 1 has held funds 1.5");
+        let err = run_parallel(config_with(input.as_bytes(), Options { self_check: true, ..Options::default() }), 2).unwrap_err();
+        assert!(matches!(err, EngineError::Inconsistent(errors) if errors.len() == 1));
+
+        // Nothing is written when the check fails.
+        let mut output = Vec::new();
+        let result = run(ConfigBuilder::new().reader(input.as_bytes()).writer(&mut output).self_check(true).build());
+        assert!(matches!(result, Err(EngineError::Inconsistent(_))));
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn process_with_self_check_of_written_output() {
+        // The available and held funds are rounded up, but not their total.
+        let input = "type,client,tx,amount\ndeposit,1,1,1.00005\ndeposit,1,2,1.00005\ndispute,1,2,\n";
+        let writer = run(config_with(input.as_bytes(), Options { rounding_mode: RoundingMode::HalfUp, ..Options::default() })).unwrap();
+        assert_eq!("client,available,held,total,locked\n1,1.0001,1.0001,2.0001,false\n", std::str::from_utf8(&writer).unwrap());
+        let err = run(config_with(input.as_bytes(), Options { rounding_mode: RoundingMode::HalfUp, self_check: true, ..Options::default() })).unwrap_err();
+        assert_eq!(err.to_string(), "The client accounts are inconsistent: the written total funds 2.0001 of the client account 1 aren't its available and held funds");
+
+        // The client accounts are read back in each output format, with the extra columns and rows.
+        let input = include_str!("../sample.csv").replace(',', ";");
+        for output_format in [OutputFormat::Csv, OutputFormat::Table] {
+            let writer = run(config_with(input.as_bytes(), Options { output_format, include_tx_count: true, include_totals: true, self_check: true, delimiter: b';', ..Options::default() }));
+            assert!(writer.is_ok(), "{:?}", writer.err());
+        }
+        let writer = run(config_with(input.as_bytes(), Options { output_format: OutputFormat::Json, include_locked_by: true, self_check: true, delimiter: b';', ..Options::default() }));
+        assert!(writer.is_ok(), "{:?}", writer.err());
+        let written = output_reader::read_accounts(b"client,available\n1,1.0\n", OutputFormat::Csv, &WriterOptions::default());
+        assert_eq!(written, Err(String::from("CSV deserialize error: record 1 (line: 2, byte: 17): missing field `held`")));
+    }
+
+    #[test]
+    fn process_jsonl_as_csv() {
+        let input =
+            "{\"type\":\"deposit\",\"client\":1,\"tx\":1,\"amount\":\"1.0\"}\n\
+            {\"type\":\"withdrawal\",\"client\":1,\"tx\":2,\"amount\":\"1.0\"}\n\
+            {\"type\":\"dispute\",\"client\":1,\"tx\":1}\n\
+            {\"type\":\"resolve\",\"client\":1,\"tx\":1}\n\
+            {\"type\":\"deposit\",\"client\":1,\"tx\":3,\"amount\":\"1.0\"}\n\
+            {\"type\":\"withdrawal\",\"client\":1,\"tx\":4,\"amount\":\"1.0\"}\n\
+            {\"type\":\"dispute\",\"client\":1,\"tx\":3}\n\
+            {\"type\":\"chargeback\",\"client\":1,\"tx\":3}\n";
+        let expected = run(config(include_str!("../sample.csv").as_bytes())).unwrap();
+        let writer = run(config_with(input.as_bytes(), Options { input_format: InputFormat::Jsonl, ..Options::default() })).unwrap();
+        assert_eq!(std::str::from_utf8(&expected).unwrap(), std::str::from_utf8(&writer).unwrap());
+        let writer = run_parallel(config_with(input.as_bytes(), Options { input_format: InputFormat::Jsonl, ..Options::default() }), 2).unwrap();
+        assert_eq!(std::str::from_utf8(&expected).unwrap(), std::str::from_utf8(&writer).unwrap());
+    }
+
+    #[test]
+    fn process_transactions_after_locked_account() {
+        let reader =
+            "type,client,tx,amount\n\
+            deposit,1,1,1.0\n\
+            dispute,1,1,\n\
+            chargeback,1,1,\n\
+            deposit,1,2,1.0\n\
+            withdrawal,1,3,1.0\n".as_bytes();
+        let writer = run(config(reader)).unwrap();
+        assert_eq!("client,available,held,total,locked\n1,0.0000,0.0000,0.0000,true\n", std::str::from_utf8(&writer).unwrap());
     }
 }
\ No newline at end of file
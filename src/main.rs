@@ -1,6 +1,14 @@
 //! # Payment engine cli
 //!
-//! The command takes a single filename for a csv file with the following format:
+//! The command takes a single filename for a csv file, or reads it from stdin if there is none.
+//! The output can be restricted to some clients with `--clients` and comma separated client ids,
+//! and written to a file instead of stdout with `-o` or `--output`. The reasons why transactions are
+//! ignored are logged on stderr with `RUST_LOG=debug`. With `--types`, the version of the engine and
+//! the supported transaction types are printed instead. With `--round-trip`, the output is read back
+//! and checked before being written, failing if e.g. a locked account has held funds. With
+//! `--stats`, a summary of the run, with the numbers of disputes opened, resolved and charged back,
+//! is printed on stderr.
+//! The csv file has the following format:
 //!
 //! > type,client,tx,amount
 //! > deposit,1,1,1.0
@@ -18,18 +26,53 @@
 //!
 //! ```bash
 //! cargo run -- transactions.csv
+//! cat transactions.csv | cargo run
+//! cargo run -- transactions.csv --clients 1,2
+//! cargo run -- transactions.csv -o results.csv
+//! RUST_LOG=debug cargo run -- transactions.csv
+//! cargo run -- transactions.csv --round-trip
+//! cargo run -- transactions.csv --stats
+//! cargo run -- --types
 //! ```
 //!
 
 use std::{env};
-use std::fs::File;
-use std::io::Stdout;
 use std::process;
 
-use payments_engine_rs::{Config, run};
+use payments_engine_rs::{record_types, Config, run};
+
+/// Logger writing the messages on stderr.
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("[{}] {}", record.level(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
 
 fn main() {
-    let config = Config::<File, Stdout>::new(env::args()).unwrap_or_else(|err| {
+    if env::args().any(|arg| arg == "--types") {
+        println!("{} {}\n", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+        print!("{}", record_types());
+        return;
+    }
+
+    // Nothing is logged unless the level is set with RUST_LOG, e.g. `RUST_LOG=debug`.
+    if let Some(level) = env::var("RUST_LOG").ok().and_then(|level| level.parse().ok()) {
+        if log::set_logger(&StderrLogger).is_ok() {
+            log::set_max_level(level);
+        }
+    }
+
+    let config = Config::from_args(env::args()).unwrap_or_else(|err| {
         eprintln!("Problem parsing arguments: {}", err);
         process::exit(1);
     });
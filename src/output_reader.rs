@@ -0,0 +1,71 @@
+//! # Reader of the written client accounts, for the self check.
+
+use serde::Deserialize;
+use crate::amount::Amount;
+use crate::csv_writer::WriterOptions;
+use crate::transaction_manager::{AccountSummary, ClientId};
+use crate::OutputFormat;
+
+/// The label of the totals row in the first column.
+const TOTALS_LABEL: &str = "TOTAL";
+
+/// A written client account, the extra columns being ignored.
+#[derive(Deserialize)]
+struct Row {
+    client: ClientId,
+    available: Amount,
+    held: Amount,
+    total: Amount,
+    locked: bool,
+}
+
+impl From<Row> for AccountSummary {
+    fn from(row: Row) -> AccountSummary {
+        AccountSummary {
+            client: row.client,
+            available: row.available,
+            held: row.held,
+            total: row.total,
+            locked: row.locked,
+        }
+    }
+}
+
+/// Read back the client accounts written in the output format with the options, as written and
+/// without the totals row, or the reason they can't be read.
+pub(crate) fn read_accounts(
+    output: &[u8],
+    output_format: OutputFormat,
+    options: &WriterOptions,
+) -> Result<Vec<AccountSummary>, String> {
+    let rows: Vec<Row> = match output_format {
+        OutputFormat::Csv => {
+            let mut csv_reader = csv::ReaderBuilder::new()
+                .delimiter(options.delimiter)
+                .flexible(true) // The totals row has empty extra columns
+                .from_reader(output);
+            let headers = csv_reader.headers().map_err(|err| err.to_string())?.clone();
+            let mut rows = Vec::new();
+            for record in csv_reader.records() {
+                let record = record.map_err(|err| err.to_string())?;
+                if record.get(0) != Some(TOTALS_LABEL) {
+                    rows.push(record.deserialize(Some(&headers)).map_err(|err| err.to_string())?);
+                }
+            }
+            rows
+        }
+        OutputFormat::Json => serde_json::from_slice(output).map_err(|err| err.to_string())?,
+        OutputFormat::Table => {
+            let output = std::str::from_utf8(output).map_err(|err| err.to_string())?;
+            // The cells of the rows between the borders, the first row being the header.
+            let mut lines = output.lines()
+                .filter(|line| line.starts_with('|'))
+                .map(|line| line.trim_matches('|').split('|').map(str::trim).collect::<csv::StringRecord>());
+            let headers = lines.next().ok_or("Missing header in the table")?;
+            lines.filter(|record| record.get(0) != Some(TOTALS_LABEL))
+                .map(|record| record.deserialize(Some(&headers)).map_err(|err| err.to_string()))
+                .collect::<Result<_, _>>()?
+        }
+    };
+    Ok(rows.into_iter().map(AccountSummary::from).collect())
+}
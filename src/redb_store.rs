@@ -0,0 +1,163 @@
+//! # Disk-backed store of the transactions.
+
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use redb::{Database, Durability, ReadableDatabase, TableDefinition};
+use crate::error::EngineError;
+use crate::transaction_manager::{ClientId, Transaction, TransactionState};
+use crate::transaction_store::TransactionStore;
+
+/// The transactions serialized as JSON, indexed by client id in the upper bits and transaction id
+/// in the lower bits.
+const TRANSACTIONS: TableDefinition<u64, &[u8]> = TableDefinition::new("transactions");
+
+/// Store of the transactions in a redb database.
+///
+/// The stores of all the client accounts share the same database. The writes aren't made durable,
+/// as the database only replaces the memory of the transaction manager during the run.
+///
+/// ## Example
+///
+/// ```
+/// use payments_engine_rs::{RedbStore, Transaction, TransactionManager};
+/// let mut transaction_manager = TransactionManager::with_store(RedbStore::in_memory().unwrap());
+/// transaction_manager.process_transaction(Transaction::deposit(1, 1, "1.0".parse().unwrap())).unwrap();
+/// transaction_manager.process_transaction(Transaction::dispute(1, 1)).unwrap();
+/// assert_eq!(transaction_manager.held(1), Some("1.0".parse().unwrap()));
+/// ```
+#[derive(Clone)]
+pub struct RedbStore {
+    database: Arc<Database>,
+    client: ClientId,
+}
+
+impl RedbStore {
+    /// Create the database at the given path, replacing any existing one.
+    pub fn create(path: impl AsRef<Path>) -> Result<RedbStore, EngineError> {
+        RedbStore::new(Database::create(path).map_err(redb_error)?)
+    }
+
+    /// Create the database in memory.
+    pub fn in_memory() -> Result<RedbStore, EngineError> {
+        let database = Database::builder()
+            .create_with_backend(redb::backends::InMemoryBackend::new())
+            .map_err(redb_error)?;
+        RedbStore::new(database)
+    }
+
+    fn new(database: Database) -> Result<RedbStore, EngineError> {
+        // The table must exist to be read.
+        let write_transaction = database.begin_write().map_err(redb_error)?;
+        write_transaction.delete_table(TRANSACTIONS).map_err(redb_error)?;
+        write_transaction.open_table(TRANSACTIONS).map_err(redb_error)?;
+        write_transaction.commit().map_err(redb_error)?;
+        Ok(RedbStore { database: Arc::new(database), client: 0 })
+    }
+
+    fn key(&self, tx: u32) -> u64 {
+        (self.client as u64) << 32 | tx as u64
+    }
+
+    /// Write the transaction to the database.
+    fn write(&self, key: u64, transaction: Option<&Transaction>) -> Result<(), EngineError> {
+        let mut write_transaction = self.database.begin_write().map_err(redb_error)?;
+        write_transaction.set_durability(Durability::None).map_err(redb_error)?;
+        {
+            let mut table = write_transaction.open_table(TRANSACTIONS).map_err(redb_error)?;
+            match transaction {
+                Some(transaction) => {
+                    table.insert(key, serde_json::to_vec(transaction)?.as_slice()).map_err(redb_error)?;
+                }
+                None => {
+                    table.remove(key).map_err(redb_error)?;
+                }
+            }
+        }
+        write_transaction.commit().map_err(redb_error)
+    }
+}
+
+impl TransactionStore for RedbStore {
+    fn for_client(&self, client: ClientId) -> RedbStore {
+        RedbStore { database: self.database.clone(), client }
+    }
+
+    fn insert(&mut self, transaction: Transaction) -> Result<(), EngineError> {
+        self.write(self.key(transaction.tx()), Some(&transaction))
+    }
+
+    fn get(&self, tx: u32) -> Result<Option<Transaction>, EngineError> {
+        let read_transaction = self.database.begin_read().map_err(redb_error)?;
+        let table = read_transaction.open_table(TRANSACTIONS).map_err(redb_error)?;
+        match table.get(self.key(tx)).map_err(redb_error)? {
+            Some(value) => Ok(Some(serde_json::from_slice(value.value())?)),
+            None => Ok(None),
+        }
+    }
+
+    fn set_state(&mut self, tx: u32, state: TransactionState) -> Result<(), EngineError> {
+        if let Some(mut transaction) = self.get(tx)? {
+            transaction.set_state(state);
+            self.write(self.key(tx), Some(&transaction))?;
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, tx: u32) -> Result<(), EngineError> {
+        self.write(self.key(tx), None)
+    }
+}
+
+/// Convert the errors of the database into I/O errors.
+fn redb_error(err: impl Into<redb::Error>) -> EngineError {
+    EngineError::Io(io::Error::other(err.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::csv_reader::{CsvReader, ReaderOptions};
+    use crate::TransactionManager;
+
+    /// Process the csv with the given transaction manager.
+    fn process<S: TransactionStore>(transaction_manager: &mut TransactionManager<S>, input: &str) {
+        let mut csv_reader = CsvReader::new(input.as_bytes(), ReaderOptions::default());
+        while let Some(transaction) = csv_reader.next().unwrap() {
+            transaction_manager.process_transaction(transaction).unwrap();
+        }
+    }
+
+    #[test]
+    fn same_results_as_in_memory_store() {
+        let input = format!("{}\ndeposit,2,5,3.0\nwithdrawal,2,6,1.0\ndispute,2,6,\nresolve,2,6,\ndispute,2,5,\n",
+            include_str!("../sample.csv").trim_end());
+        let mut in_memory = TransactionManager::new();
+        process(&mut in_memory, &input);
+        let mut on_disk = TransactionManager::with_store(RedbStore::in_memory().unwrap());
+        process(&mut on_disk, &input);
+
+        assert_eq!(on_disk.processed(), in_memory.processed());
+        assert_eq!(on_disk.ignored(), in_memory.ignored());
+        for client in [1, 2] {
+            let (expected, actual) = (in_memory.account(client).unwrap(), on_disk.account(client).unwrap());
+            assert_eq!(actual.available, expected.available);
+            assert_eq!(actual.held, expected.held);
+            assert_eq!(actual.locked, expected.locked);
+        }
+        assert!(on_disk.account(1).unwrap().locked);
+        assert_eq!(on_disk.held(2), Some("3.0".parse().unwrap()));
+    }
+
+    #[test]
+    fn store_transactions_per_client() {
+        let store = RedbStore::in_memory().unwrap();
+        let (mut client_1, client_2) = (store.for_client(1), store.for_client(2));
+        client_1.insert(Transaction::deposit(1, 1, "1.0".parse().unwrap())).unwrap();
+        client_1.set_state(1, TransactionState::Disputed { amount: "1.0".parse().unwrap() }).unwrap();
+        assert!(matches!(client_1.get(1).unwrap().unwrap().state(), TransactionState::Disputed { .. }));
+        assert!(client_2.get(1).unwrap().is_none());
+        client_1.remove(1).unwrap();
+        assert!(client_1.get(1).unwrap().is_none());
+    }
+}
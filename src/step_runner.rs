@@ -0,0 +1,77 @@
+//! # Step by step processing of transactions.
+
+use std::io;
+use crate::csv_reader::{CsvReader, ReaderOptions};
+use crate::error::EngineError;
+use crate::transaction_manager::{ProcessedOutcome, TransactionManager};
+
+/// Runs the payments engine one transaction at a time.
+///
+/// The client accounts can be inspected through `manager` between the steps.
+///
+/// ## Example
+///
+/// ```
+/// use payments_engine_rs::{ProcessedOutcome, StepRunner};
+/// let mut step_runner = StepRunner::new("type,client,tx,amount\ndeposit,1,1,1.0".as_bytes());
+/// assert_eq!(step_runner.step().unwrap(), Some(ProcessedOutcome::Applied));
+/// assert_eq!(step_runner.manager.client_account_index[&1].available.to_string(), "1.0");
+/// assert_eq!(step_runner.step().unwrap(), None);
+/// ```
+pub struct StepRunner<R: io::Read> {
+    csv_reader: CsvReader<R>,
+    pub manager: TransactionManager,
+}
+
+impl<R: io::Read> StepRunner<R> {
+    pub fn new(reader: R) -> StepRunner<R> {
+        StepRunner {
+            csv_reader: CsvReader::new(reader, ReaderOptions::default()),
+            manager: TransactionManager::new(),
+        }
+    }
+
+    /// Read and process the next transaction, or return `None` at the end of the csv.
+    pub fn step(&mut self) -> Result<Option<ProcessedOutcome>, EngineError> {
+        match self.csv_reader.next()? {
+            Some(transaction) => Ok(Some(self.manager.process_transaction(transaction)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IgnoreReason;
+
+    #[test]
+    fn step_through_sample() {
+        let mut step_runner = StepRunner::new(include_str!("../sample.csv").as_bytes());
+        let expected = [
+            (ProcessedOutcome::Applied, "1.0", "0.0"),
+            (ProcessedOutcome::Applied, "0.0", "0.0"),
+            (ProcessedOutcome::Applied, "-1.0", "1.0"),
+            (ProcessedOutcome::Applied, "0.0", "0.0"),
+            (ProcessedOutcome::Applied, "1.0", "0.0"),
+            (ProcessedOutcome::Applied, "0.0", "0.0"),
+            (ProcessedOutcome::Applied, "-1.0", "1.0"),
+            (ProcessedOutcome::Locked, "-1.0", "0.0"),
+        ];
+        for (outcome, available, held) in expected {
+            assert_eq!(step_runner.step().unwrap(), Some(outcome));
+            let client_account = &step_runner.manager.client_account_index[&1];
+            assert_eq!(client_account.available.to_string(), available);
+            assert_eq!(client_account.held.to_string(), held);
+        }
+        assert_eq!(step_runner.step().unwrap(), None);
+        assert!(step_runner.manager.client_account_index[&1].locked);
+    }
+
+    #[test]
+    fn step_ignored_transaction() {
+        let mut step_runner = StepRunner::new("type,client,tx,amount\nwithdrawal,1,1,1.0\n".as_bytes());
+        assert_eq!(step_runner.step().unwrap(), Some(ProcessedOutcome::Ignored(IgnoreReason::InsufficientFunds)));
+        assert_eq!(step_runner.step().unwrap(), None);
+    }
+}
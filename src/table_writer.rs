@@ -0,0 +1,103 @@
+//! # Table Writer utilities for client accounts.
+
+use std::io::Write;
+use crate::csv_writer::{self, Record, Totals, WriterOptions};
+use crate::error::EngineError;
+use crate::transaction_manager::ClientAccount;
+
+/// Table writer for client accounts, for human inspection.
+///
+/// The client accounts are written in a table with borders, whose columns are aligned in a
+/// monospace font. As the width of the columns depends on all the client accounts, they are only
+/// written to the underlying writer with the whole table. The totals row, if any, is separated
+/// from the client accounts by a border.
+pub struct TableWriter<W: Write> {
+    writer: W,
+    rows: Vec<Vec<String>>,
+    totals: Totals,
+    options: WriterOptions,
+}
+
+impl<W: Write> TableWriter<W> {
+    pub fn new(writer: W, options: WriterOptions) -> TableWriter<W> {
+        TableWriter {
+            writer,
+            rows: Vec::new(),
+            totals: Totals::default(),
+            options,
+        }
+    }
+
+    /// Add a single client account to the table.
+    pub fn write(&mut self, client_account: &ClientAccount) -> Result<(), EngineError> {
+        let record = Record::new(client_account, &self.options)?;
+        let mut row = vec![record.client.to_string(), record.available, record.held, record.total, record.locked.to_string()];
+        if let Some(tx_count) = record.tx_count {
+            row.push(tx_count.to_string());
+        }
+        if let Some(locked_by) = record.locked_by {
+            row.push(locked_by.map_or_else(String::new, |locked_by| locked_by.to_string()));
+        }
+        if let Some(last_tx) = record.last_tx {
+            row.push(last_tx.to_string());
+        }
+        self.rows.push(row);
+        self.totals.add(client_account);
+        Ok(())
+    }
+
+    /// Write the table, flush it and retrieve the underlying writer.
+    pub fn into_inner(mut self) -> Result<W, EngineError> {
+        let header = csv_writer::header(&self.options);
+        let totals = self.options.include_totals.then(|| self.totals.row(&self.options));
+        let mut widths: Vec<usize> = header.iter().map(|name| name.len()).collect();
+        for row in self.rows.iter().chain(&totals) {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.len());
+            }
+        }
+
+        let border = format_border(&widths);
+        writeln!(self.writer, "{}", border)?;
+        writeln!(self.writer, "{}", format_row(&header, &widths))?;
+        writeln!(self.writer, "{}", border)?;
+        for row in &self.rows {
+            writeln!(self.writer, "{}", format_row(row, &widths))?;
+        }
+        if !self.rows.is_empty() {
+            writeln!(self.writer, "{}", border)?;
+        }
+        if let Some(totals) = totals {
+            writeln!(self.writer, "{}", format_row(&totals, &widths))?;
+            writeln!(self.writer, "{}", border)?;
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// The border between the header and the rows of the table, e.g. `+----+------+`.
+fn format_border(widths: &[usize]) -> String {
+    let mut border = String::from("+");
+    for width in widths {
+        border.push_str(&"-".repeat(width + 2));
+        border.push('+');
+    }
+    border
+}
+
+/// A row of the table, e.g. `| 1 | false |`, with the cells padded to the widths of the columns.
+///
+/// The numbers are aligned to the right, and the text to the left.
+fn format_row<S: AsRef<str>>(cells: &[S], widths: &[usize]) -> String {
+    let mut row = String::from("|");
+    for (cell, width) in cells.iter().zip(widths) {
+        let cell = cell.as_ref();
+        if cell.starts_with(|c: char| c.is_ascii_digit() || c == '-') {
+            row.push_str(&format!(" {:>width$} |", cell, width = width));
+        } else {
+            row.push_str(&format!(" {:<width$} |", cell, width = width));
+        }
+    }
+    row
+}
@@ -1,309 +1,1859 @@
 //! # Transaction manager
 //!
 //! The transaction manager processes transactions and generates an index of client accounts.
+//!
+//! ## Disputes
+//!
+//! Both deposits and withdrawals can be disputed. The balances are updated as follows, where
+//! `amount` is the amount of the disputed transaction.
+//!
+//! | Disputed transaction | Dispute              | Resolve              | Chargeback                     |
+//! |----------------------|----------------------|----------------------|--------------------------------|
+//! | deposit              | available -= amount  | available += amount  | held -= amount                 |
+//! |                      | held += amount       | held -= amount       | locked = true                  |
+//! | withdrawal           | held += amount       | held -= amount       | held -= amount                 |
+//! |                      |                      |                      | available += amount            |
+//! |                      |                      |                      | locked = true                  |
+//!
+//! Disputes, resolves and chargebacks can carry an optional amount, not exceeding the disputed
+//! amount, to only apply to a portion of the transaction, e.g. for payment networks allowing
+//! partial chargebacks. The balances are then updated with this amount instead of the full one.
+//!
+//! Disputing a deposit holds funds that are still in the account, so they are no longer available.
+//! Disputing a withdrawal holds the withdrawn funds that the client claims back: they are not
+//! available until the dispute is settled, but count towards the total. Resolving the dispute
+//! releases the hold, and charging it back returns the funds to the available balance.
+//!
+//! An optional hook, see [`TransactionManager::with_on_hold`], adjusts the amount released when a
+//! dispute is resolved, e.g. to charge a fee for the time the funds were held. The available funds
+//! are then credited with the adjusted amount instead of the resolved one for a deposit, and
+//! debited with the difference for a withdrawal, whose held funds were never taken from them.
+//!
+//! A withdrawal can therefore be disputed even if the account has been drained since: its dispute
+//! doesn't take anything from the available funds, which never go negative because of it. E.g.
+//! after a deposit of 10 and a withdrawal of 10, disputing the withdrawal leaves 0 available and 10
+//! held, for a total of 10.
+//!
+//! A chargeback locks the client account. Any further transaction on a locked account, including
+//! disputes, resolves and chargebacks of its other transactions, is ignored.
+//!
+//! The intended invariant is that a locked account has no held funds: the chargeback releases the
+//! funds it charges back, and nothing else should be pending. It doesn't hold when the chargeback
+//! is partial, or when other transactions of the client are still disputed, as their funds stay held
+//! and can't be settled anymore. Such accounts are kept as is, and reported by
+//! [`crate::self_check`] for manual review.
+//!
+//! A client account can also be frozen administratively, independently of the chargebacks, until it
+//! is thawed. The deposits and withdrawals on a frozen account are ignored, but its disputes are
+//! still settled.
+//!
+//! Held funds are never withdrawable: a withdrawal is only executed if the available funds, which
+//! exclude the held funds, cover its amount. An account with a credit limit can be overdrawn: the
+//! available funds can then go down to minus the credit limit. The held funds never become negative, but the
+//! available funds can: disputing or charging back a deposit that was already withdrawn leaves the
+//! client owing the amount.
+//!
+//! ## Transaction states
+//!
+//! The disputes move the deposits and withdrawals through the following states:
+//!
+//! * **executed** → **disputed** - on dispute, of the full amount or of the given portion.
+//! * **disputed** → **resolved** - on resolve of the whole disputed amount.
+//! * **disputed** → **disputed** - on partial resolve or partial chargeback, the remainder of the
+//!   disputed amount stays disputed and held. A partial chargeback locks the account anyway, so its
+//!   remainder stays held.
+//! * **resolved** → **disputed** - on dispute, a resolved transaction can be disputed again.
+//! * **disputed** → **charged back** - on chargeback of the whole disputed amount. This state is
+//!   terminal, charged back transactions are kept in the transaction index of the client account
+//!   so that the disputes, resolves and chargebacks referencing them are recorded as such.
+//!
+//! Any other transition is ignored, as are the amounts exceeding the disputed amount. With a dispute
+//! window, see [`TransactionManager::with_dispute_window`], the disputes of the transactions
+//! processed too long before are ignored as well.
 
-use std::collections::HashMap;
-use std::error::Error;
-use std::fmt;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use serde::{Deserialize, Serialize};
+use crate::amount::Amount;
+use crate::csv_reader;
+use crate::error::EngineError;
+use crate::transaction_store::TransactionStore;
 
 /// The state of the transactions.
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum TransactionState {
     Executed,
-    Disputed,
+    Disputed {
+        amount: Amount, // Disputed portion of the amount of the transaction
+    },
     Resolved,
     Chargedback
 }
 
 /// The types of transactions with related data.
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum TransactionType {
     Deposit {
-        amount: f64,
+        amount: Amount,
     },
     Withdrawal {
-        amount: f64,
+        amount: Amount,
+    },
+    Dispute {
+        amount: Option<Amount>, // Disputed portion, the full amount if `None`
+    },
+    Resolve {
+        amount: Option<Amount>, // Resolved portion, the whole disputed amount if `None`
     },
-    Dispute,
-    Resolve,
-    Chargeback,
+    Chargeback {
+        amount: Option<Amount>, // Charged back portion, the whole disputed amount if `None`
+    },
+    CreditLimit {
+        amount: Amount, // Amount by which the available funds can be overdrawn
+    },
+    Freeze,
+    Thaw,
 }
 
+/// A hook adjusting the amount released when a dispute is resolved, e.g. to subtract a fee.
+pub type HoldHook = Box<dyn Fn(Amount) -> Amount + Send>;
+
+/// The id of a client, the same for all the transactions of its account.
+pub type ClientId = u32;
+
 /// The transaction model.
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Transaction {
     transaction_type: TransactionType,
-    client: u16, // Client id
+    client: ClientId, // Client id
     tx: u32, // Transaction id
-    state: TransactionState
+    state: TransactionState,
+    #[serde(default)]
+    seq: u64, // Sequence number assigned by the transaction manager
 }
 
 impl Transaction {
-    pub fn new(transaction_type: TransactionType, client: u16, tx: u32) -> Transaction {
+    pub fn new(transaction_type: TransactionType, client: ClientId, tx: u32) -> Transaction {
         Transaction {
             transaction_type,
             client,
             tx,
-            state: TransactionState::Executed
+            state: TransactionState::Executed,
+            seq: 0,
         }
     }
+
+    /// Create a deposit of the given amount into the client account.
+    pub fn deposit(client: ClientId, tx: u32, amount: Amount) -> Transaction {
+        Transaction::new(TransactionType::Deposit { amount }, client, tx)
+    }
+
+    /// Create a withdrawal of the given amount from the client account.
+    pub fn withdrawal(client: ClientId, tx: u32, amount: Amount) -> Transaction {
+        Transaction::new(TransactionType::Withdrawal { amount }, client, tx)
+    }
+
+    /// Create a transaction setting the credit limit of the client account.
+    pub fn credit_limit(client: ClientId, tx: u32, amount: Amount) -> Transaction {
+        Transaction::new(TransactionType::CreditLimit { amount }, client, tx)
+    }
+
+    /// Create a transaction freezing the client account.
+    pub fn freeze(client: ClientId, tx: u32) -> Transaction {
+        Transaction::new(TransactionType::Freeze, client, tx)
+    }
+
+    /// Create a transaction thawing the frozen client account.
+    pub fn thaw(client: ClientId, tx: u32) -> Transaction {
+        Transaction::new(TransactionType::Thaw, client, tx)
+    }
+
+    /// Create a dispute of the transaction with the given transaction id.
+    pub fn dispute(client: ClientId, tx: u32) -> Transaction {
+        Transaction::new(TransactionType::Dispute { amount: None }, client, tx)
+    }
+
+    /// Create a dispute of a portion of the transaction with the given transaction id.
+    pub fn partial_dispute(client: ClientId, tx: u32, amount: Amount) -> Transaction {
+        Transaction::new(TransactionType::Dispute { amount: Some(amount) }, client, tx)
+    }
+
+    /// Create a resolve of the disputed transaction with the given transaction id.
+    pub fn resolve(client: ClientId, tx: u32) -> Transaction {
+        Transaction::new(TransactionType::Resolve { amount: None }, client, tx)
+    }
+
+    /// Create a resolve of a portion of the disputed transaction with the given transaction id.
+    pub fn partial_resolve(client: ClientId, tx: u32, amount: Amount) -> Transaction {
+        Transaction::new(TransactionType::Resolve { amount: Some(amount) }, client, tx)
+    }
+
+    /// Create a chargeback of the disputed transaction with the given transaction id.
+    pub fn chargeback(client: ClientId, tx: u32) -> Transaction {
+        Transaction::new(TransactionType::Chargeback { amount: None }, client, tx)
+    }
+
+    /// Create a chargeback of a portion of the disputed transaction with the given transaction id.
+    pub fn partial_chargeback(client: ClientId, tx: u32, amount: Amount) -> Transaction {
+        Transaction::new(TransactionType::Chargeback { amount: Some(amount) }, client, tx)
+    }
+
+    pub fn transaction_type(&self) -> &TransactionType {
+        &self.transaction_type
+    }
+
+    pub fn client(&self) -> ClientId {
+        self.client
+    }
+
+    pub fn tx(&self) -> u32 {
+        self.tx
+    }
+
+    pub fn state(&self) -> &TransactionState {
+        &self.state
+    }
+
+    /// The sequence number of the transaction, assigned by the transaction manager in processing
+    /// order starting at 1, or 0 if it wasn't processed yet.
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// The amount of the deposits and withdrawals, `None` for the other transactions.
+    pub fn amount(&self) -> Option<Amount> {
+        match self.transaction_type {
+            TransactionType::Deposit { amount } | TransactionType::Withdrawal { amount } => Some(amount),
+            TransactionType::Dispute { .. } | TransactionType::Resolve { .. } | TransactionType::Chargeback { .. }
+                | TransactionType::CreditLimit { .. } | TransactionType::Freeze | TransactionType::Thaw => None,
+        }
+    }
+
+    pub(crate) fn set_state(&mut self, state: TransactionState) {
+        self.state = state;
+    }
+
+    pub(crate) fn set_seq(&mut self, seq: u64) {
+        self.seq = seq;
+    }
 }
 
-#[derive(Debug)]
-pub struct ClientAccount {
-    pub client: u16,
-    pub available: f64,
-    pub held: f64,
+/// The account of a client, with the store `S` of its disputable transactions.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClientAccount<S = HashMap<u32, Transaction>> {
+    pub client: ClientId,
+    pub available: Amount,
+    pub held: Amount,
     pub locked: bool,
-    pub transaction_index: HashMap<u32, Transaction>,
+    pub locked_by: Option<u32>, // Transaction id of the chargeback that locked the account
+    pub credit_limit: Amount, // Amount by which the available funds can be overdrawn
+    pub frozen: bool, // Whether the account is frozen administratively, independently of the chargebacks
+    pub transaction_index: S,
+    pub first_seen: u64, // Order in which the client was first seen by the transaction manager
+    pub tx_count: u64, // Number of deposits and withdrawals executed on the account
+    pub last_tx: u32, // Transaction id of the last transaction applied to the account
 }
 
 impl ClientAccount {
     pub fn new(
-        client: u16,
-        available: f64,
-        held: f64,
+        client: ClientId,
+        available: Amount,
+        held: Amount,
     ) -> ClientAccount {
         ClientAccount {
             client,
             available,
             held,
             locked: false,
+            locked_by: None,
+            credit_limit: Amount::ZERO,
+            frozen: false,
             transaction_index: HashMap::new(),
+            first_seen: 0,
+            tx_count: 0,
+            last_tx: 0,
         }
     }
-}
+}
+
+impl<S> ClientAccount<S> {
+    /// Create an empty account for the client storing its transactions in the given store.
+    pub fn with_store(client: ClientId, transaction_index: S) -> ClientAccount<S> {
+        ClientAccount {
+            client,
+            available: Amount::ZERO,
+            held: Amount::ZERO,
+            locked: false,
+            locked_by: None,
+            credit_limit: Amount::ZERO,
+            frozen: false,
+            transaction_index,
+            first_seen: 0,
+            tx_count: 0,
+            last_tx: 0,
+        }
+    }
+
+    /// The total funds of the account, available and held, or `None` if they overflow.
+    ///
+    /// The transaction manager ignores the transactions that would make the total overflow.
+    pub fn total(&self) -> Option<Amount> {
+        self.available.checked_add(self.held)
+    }
+
+    /// The summary of the balances of the account, without its transactions, or `None` if its
+    /// total funds overflow.
+    pub fn summary(&self) -> Option<AccountSummary> {
+        Some(AccountSummary {
+            client: self.client,
+            available: self.available,
+            held: self.held,
+            total: self.total()?,
+            locked: self.locked,
+        })
+    }
+}
+
+/// The balances of a client account, as written in the output, e.g. to hand them out without the
+/// transactions of the account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccountSummary {
+    pub client: ClientId,
+    pub available: Amount,
+    pub held: Amount,
+    pub total: Amount,
+    pub locked: bool,
+}
+
+/// The reasons for which a transaction can be ignored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum IgnoreReason {
+    /// The client account is locked.
+    AccountLocked,
+    /// The client account is frozen.
+    AccountFrozen,
+    /// The available funds are insufficient for the withdrawal.
+    InsufficientFunds,
+    /// The transaction would overflow the balance of the client account.
+    Overflow,
+    /// The amount of the deposit or withdrawal is zero.
+    ZeroAmount,
+    /// The client id is reserved.
+    ReservedClient,
+    /// The referenced transaction doesn't exist, or belongs to another client as the transactions
+    /// are only looked up in the transaction index of the referencing client.
+    UnknownTransaction,
+    /// The referenced transaction can't be disputed as it is already disputed.
+    NotDisputable,
+    /// The referenced transaction isn't disputed.
+    NotDisputed,
+    /// The referenced transaction is already charged back.
+    AlreadyChargedBack,
+    /// The amount of the dispute, resolve or chargeback exceeds the disputed amount.
+    ExcessiveAmount,
+    /// The transaction id of the deposit or withdrawal was already used by another transaction,
+    /// applied or not: the ids of the ignored deposits and withdrawals are reserved too.
+    DuplicateTransaction,
+    /// The referenced transaction is older than the dispute window.
+    DisputeWindowExpired,
+    /// The transaction would create a client account beyond the maximum number of clients.
+    ClientLimitReached,
+}
+
+/// The ways to handle the withdrawals exceeding the available funds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum OverdrawPolicy {
+    /// Ignore the withdrawal and record it with [`IgnoreReason::InsufficientFunds`].
+    #[default]
+    Ignore,
+    /// Stop the processing with [`EngineError::Overdraw`], e.g. for strict feeds.
+    Error,
+}
+
+/// A transaction that has been ignored by the transaction manager.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IgnoredTransaction {
+    pub tx: u32, // Transaction id
+    pub client: ClientId, // Client id
+    pub reason: IgnoreReason,
+}
+
+/// What happened to a processed transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProcessedOutcome {
+    /// The transaction was applied to the client account.
+    Applied,
+    /// The transaction was applied and locked the client account.
+    Locked,
+    /// The transaction was ignored for the given reason.
+    Ignored(IgnoreReason),
+}
+
+/// The counts of the transactions of a batch, see [`TransactionManager::process_batch`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BatchReport {
+    pub applied: u64, // Transactions applied, including the ones locking the client account
+    pub ignored: u64, // Transactions ignored
+}
+
+/// The counts of the disputes through their lifecycle, see [`TransactionManager::dispute_stats`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct DisputeStats {
+    pub opened: u64, // Disputes applied
+    pub resolved: u64, // Disputes resolved of their whole disputed amount
+    pub charged_back: u64, // Chargebacks applied, whole or partial
+}
+
+/// Processor for transactions and the generation of the client account index.
+///
+/// ## Example
+///
+/// ```
+/// use payments_engine_rs::{Transaction, TransactionManager};
+/// let mut transaction_manager = TransactionManager::new();
+/// transaction_manager.process_transaction(Transaction::deposit(1, 1, "10.0".parse().unwrap())).unwrap();
+/// transaction_manager.process_transaction(Transaction::withdrawal(1, 2, "4.0".parse().unwrap())).unwrap();
+/// transaction_manager.process_transaction(Transaction::dispute(1, 1)).unwrap();
+///
+/// let client_account = &transaction_manager.client_account_index[&1];
+/// assert_eq!(client_account.available.to_string(), "-4.0");
+/// assert_eq!(client_account.held.to_string(), "10.0");
+/// ```
+///
+/// The transactions of the client accounts are kept in memory by default. Another
+/// [`TransactionStore`] can be used with [`TransactionManager::with_store`].
+#[derive(Default, Serialize, Deserialize)]
+pub struct TransactionManager<S = HashMap<u32, Transaction>>
+{
+    pub client_account_index: HashMap<ClientId, ClientAccount<S>>,
+    ignored: Vec<IgnoredTransaction>,
+    processed: u64,
+    #[serde(default)]
+    reserved_client_ids: HashSet<ClientId>, // Clients whose transactions are ignored
+    #[serde(default)]
+    seen_tx: HashSet<u32>, // Transaction ids of all the deposits and withdrawals, of any client, even ignored
+    #[serde(default)]
+    skip_transaction_index: bool, // Whether the deposits and withdrawals are left out of the transaction indexes
+    #[serde(default)]
+    dispute_window: Option<u64>, // Maximum number of sequence steps between a transaction and its dispute
+    #[serde(default)]
+    max_clients: Option<usize>, // Maximum number of client accounts
+    #[serde(default)]
+    overdraw_policy: OverdrawPolicy, // Handling of the withdrawals exceeding the available funds
+    #[serde(default)]
+    dispute_stats: DisputeStats,
+    #[serde(skip)]
+    on_hold: Option<HoldHook>, // Adjustment of the amounts released by the resolves
+    #[serde(skip)]
+    store: S, // Empty store from which the stores of the client accounts are created
+}
+
+impl TransactionManager {
+    pub fn new() -> TransactionManager {
+        TransactionManager::with_store(HashMap::new())
+    }
+
+    /// Load the state of a transaction manager saved with [`TransactionManager::save`].
+    pub fn load<R: io::Read>(reader: R) -> Result<TransactionManager, EngineError> {
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    /// Save the state of the transaction manager as JSON, so it can be loaded to continue processing.
+    pub fn save<W: io::Write>(&self, writer: W) -> Result<(), EngineError> {
+        serde_json::to_writer(writer, self)?;
+        Ok(())
+    }
+
+    /// Merge another transaction manager into this one, e.g. the result of processing another
+    /// partition of the transactions, as if its transactions had been processed after the ones of
+    /// this manager.
+    ///
+    /// The accounts of the same client are combined: their balances and numbers of transactions are
+    /// summed, their transaction indexes are merged, and they are locked or frozen if either account
+    /// is. The greatest credit limit is kept, and the last applied transaction is the one of the other
+    /// account if it applied any. The clients only known by the other manager are seen after the ones
+    /// of this manager.
+    ///
+    /// The transaction ids of the deposits and withdrawals must be unique across both managers, as
+    /// they are within one. On a collision the merge fails with [`EngineError::DuplicateTransaction`],
+    /// as it fails with [`EngineError::Overflow`] if the funds of a combined account overflow, and
+    /// this manager is left unchanged.
+    pub fn merge(&mut self, other: TransactionManager) -> Result<(), EngineError> {
+        if let Some(&tx) = self.seen_tx.intersection(&other.seen_tx).min() {
+            return Err(EngineError::DuplicateTransaction { tx });
+        }
+        // Check all the combined accounts before changing any of them.
+        for (client, other_account) in &other.client_account_index {
+            if let Some(client_account) = self.client_account_index.get(client) {
+                client_account.available.checked_add(other_account.available)
+                    .zip(client_account.held.checked_add(other_account.held))
+                    .and_then(|(available, held)| available.checked_add(held))
+                    .ok_or(EngineError::Overflow { client: *client })?;
+            }
+        }
+
+        let mut other_accounts: Vec<ClientAccount> = other.client_account_index.into_values().collect();
+        other_accounts.sort_by_key(|other_account| (other_account.first_seen, other_account.client));
+        for other_account in other_accounts {
+            let first_seen = self.client_account_index.len() as u64;
+            match self.client_account_index.entry(other_account.client) {
+                Entry::Occupied(entry) => {
+                    let client_account = entry.into_mut();
+                    client_account.available = client_account.available.saturating_add(other_account.available);
+                    client_account.held = client_account.held.saturating_add(other_account.held);
+                    client_account.locked |= other_account.locked;
+                    client_account.locked_by = client_account.locked_by.or(other_account.locked_by);
+                    client_account.credit_limit = client_account.credit_limit.max(other_account.credit_limit);
+                    client_account.frozen |= other_account.frozen;
+                    client_account.transaction_index.extend(other_account.transaction_index);
+                    client_account.tx_count += other_account.tx_count;
+                    // The other account may have seen no applied transaction of the client.
+                    if other_account.last_tx != 0 {
+                        client_account.last_tx = other_account.last_tx;
+                    }
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(ClientAccount { first_seen, ..other_account });
+                }
+            }
+        }
+        self.ignored.extend(other.ignored);
+        self.processed += other.processed;
+        self.seen_tx.extend(other.seen_tx);
+        self.dispute_stats.opened += other.dispute_stats.opened;
+        self.dispute_stats.resolved += other.dispute_stats.resolved;
+        self.dispute_stats.charged_back += other.dispute_stats.charged_back;
+        Ok(())
+    }
+
+    /// Drop the charged back transactions from the transaction indexes of the client accounts and
+    /// shrink them, e.g. to keep the transaction manager in memory once the whole file is processed.
+    /// The balances are unchanged, and so are the results of the transactions processed afterwards.
+    ///
+    /// The resolved transactions are kept, as they can be disputed again. The charged back ones
+    /// can't be referenced anymore as their accounts are locked, so the disputes, resolves and
+    /// chargebacks referencing them are then ignored as [`IgnoreReason::AccountLocked`] rather than
+    /// [`IgnoreReason::AlreadyChargedBack`]. Their transaction ids are still known, so they can't be
+    /// reused by new deposits or withdrawals.
+    pub fn compact(&mut self) {
+        for client_account in self.client_account_index.values_mut() {
+            client_account.transaction_index
+                .retain(|_, transaction| !matches!(transaction.state, TransactionState::Chargedback));
+            client_account.transaction_index.shrink_to_fit();
+        }
+        self.client_account_index.shrink_to_fit();
+    }
+}
+
+impl<S: TransactionStore> TransactionManager<S> {
+    /// Create a transaction manager storing the transactions of each client account in a store
+    /// created from the given one with [`TransactionStore::for_client`].
+    pub fn with_store(store: S) -> TransactionManager<S> {
+        TransactionManager {
+            client_account_index: HashMap::new(),
+            ignored: Vec::new(),
+            processed: 0,
+            reserved_client_ids: HashSet::new(),
+            seen_tx: HashSet::new(),
+            skip_transaction_index: false,
+            dispute_window: None,
+            max_clients: None,
+            overdraw_policy: OverdrawPolicy::default(),
+            dispute_stats: DisputeStats::default(),
+            on_hold: None,
+            store,
+        }
+    }
+
+    /// Ignore the transactions of the given clients, e.g. a client id used as a sentinel upstream.
+    pub fn with_reserved_client_ids(mut self, reserved_client_ids: HashSet<ClientId>) -> TransactionManager<S> {
+        self.reserved_client_ids = reserved_client_ids;
+        self
+    }
+
+    /// Keep the deposits and withdrawals in the transaction indexes of the client accounts only if
+    /// disputes are expected. Otherwise they take no memory, but the disputes, resolves and
+    /// chargebacks are all ignored as referencing unknown transactions.
+    pub fn with_disputes_expected(mut self, disputes_expected: bool) -> TransactionManager<S> {
+        self.skip_transaction_index = !disputes_expected;
+        self
+    }
+
+    /// Ignore the disputes of the transactions processed more than `dispute_window` transactions
+    /// before, according to their sequence numbers, e.g. with a window of 1 only the previous
+    /// transaction can be disputed. Without a window, any transaction can be disputed.
+    ///
+    /// ```
+    /// use payments_engine_rs::{IgnoreReason, ProcessedOutcome, Transaction, TransactionManager};
+    /// let mut transaction_manager = TransactionManager::new().with_dispute_window(Some(2));
+    /// transaction_manager.process_transaction(Transaction::deposit(1, 1, "10.0".parse().unwrap())).unwrap();
+    /// transaction_manager.process_transaction(Transaction::deposit(1, 2, "5.0".parse().unwrap())).unwrap();
+    /// transaction_manager.process_transaction(Transaction::deposit(1, 3, "1.0".parse().unwrap())).unwrap();
+    /// let outcome = transaction_manager.process_transaction(Transaction::dispute(1, 1)).unwrap();
+    /// assert_eq!(outcome, ProcessedOutcome::Ignored(IgnoreReason::DisputeWindowExpired));
+    /// let outcome = transaction_manager.process_transaction(Transaction::dispute(1, 3)).unwrap();
+    /// assert_eq!(outcome, ProcessedOutcome::Applied);
+    /// ```
+    pub fn with_dispute_window(mut self, dispute_window: Option<u64>) -> TransactionManager<S> {
+        self.dispute_window = dispute_window;
+        self
+    }
+
+    /// Ignore the transactions that would create a client account beyond `max_clients` accounts,
+    /// e.g. to bound the memory. The transactions of the existing clients are still processed.
+    ///
+    /// ```
+    /// use payments_engine_rs::{IgnoreReason, ProcessedOutcome, Transaction, TransactionManager};
+    /// let mut transaction_manager = TransactionManager::new().with_max_clients(Some(1));
+    /// transaction_manager.process_transaction(Transaction::deposit(1, 1, "10.0".parse().unwrap())).unwrap();
+    /// let outcome = transaction_manager.process_transaction(Transaction::deposit(2, 2, "5.0".parse().unwrap())).unwrap();
+    /// assert_eq!(outcome, ProcessedOutcome::Ignored(IgnoreReason::ClientLimitReached));
+    /// assert!(!transaction_manager.has_client(2));
+    /// ```
+    pub fn with_max_clients(mut self, max_clients: Option<usize>) -> TransactionManager<S> {
+        self.max_clients = max_clients;
+        self
+    }
+
+    /// Handle the withdrawals exceeding the available funds according to the `overdraw_policy`, by
+    /// default they are ignored.
+    ///
+    /// ```
+    /// use payments_engine_rs::{EngineError, OverdrawPolicy, Transaction, TransactionManager};
+    /// let mut transaction_manager = TransactionManager::new().with_overdraw_policy(OverdrawPolicy::Error);
+    /// transaction_manager.process_transaction(Transaction::deposit(1, 1, "1.0".parse().unwrap())).unwrap();
+    /// let result = transaction_manager.process_transaction(Transaction::withdrawal(1, 2, "1.5".parse().unwrap()));
+    /// assert!(matches!(result, Err(EngineError::Overdraw { client: 1, tx: 2 })));
+    /// ```
+    pub fn with_overdraw_policy(mut self, overdraw_policy: OverdrawPolicy) -> TransactionManager<S> {
+        self.overdraw_policy = overdraw_policy;
+        self
+    }
+
+    /// Adjust the amount released when a dispute is resolved with the given hook, e.g. to subtract a
+    /// flat fee. Without a hook, the whole resolved amount is released.
+    ///
+    /// ```
+    /// use payments_engine_rs::{Amount, Transaction, TransactionManager};
+    /// let fee: Amount = "0.5".parse().unwrap();
+    /// let mut transaction_manager = TransactionManager::new()
+    ///     .with_on_hold(Box::new(move |amount: Amount| amount.saturating_sub(fee)));
+    /// transaction_manager.process_transaction(Transaction::deposit(1, 1, "10.0".parse().unwrap())).unwrap();
+    /// transaction_manager.process_transaction(Transaction::dispute(1, 1)).unwrap();
+    /// transaction_manager.process_transaction(Transaction::resolve(1, 1)).unwrap();
+    /// assert_eq!(transaction_manager.available(1), Some("9.5".parse().unwrap()));
+    /// ```
+    pub fn with_on_hold(mut self, on_hold: HoldHook) -> TransactionManager<S> {
+        self.on_hold = Some(on_hold);
+        self
+    }
+
+    /// Skip the deposits and withdrawals whose transaction ids are in the log saved with
+    /// [`TransactionManager::save_tx_log`], e.g. to replay a file overlapping a previously processed
+    /// one without applying its transactions twice. They are ignored as duplicates. The disputes,
+    /// resolves and chargebacks aren't logged, so their replays are only ignored when the state of
+    /// the referenced transaction rejects them, e.g. a dispute of a disputed transaction.
+    pub fn with_tx_log<R: io::Read>(mut self, reader: R) -> Result<TransactionManager<S>, EngineError> {
+        let tx_log: HashSet<u32> = serde_json::from_reader(reader)?;
+        self.seen_tx.extend(tx_log);
+        Ok(self)
+    }
+
+    /// Save the transaction ids of the deposits and withdrawals processed so far as JSON, the ignored
+    /// ones included, so that a replay ignores them again.
+    pub fn save_tx_log<W: io::Write>(&self, writer: W) -> Result<(), EngineError> {
+        serde_json::to_writer(writer, &self.seen_tx)?;
+        Ok(())
+    }
+
+    /// The account of the given client, if it has any transaction.
+    ///
+    /// ```
+    /// use payments_engine_rs::{Transaction, TransactionManager};
+    /// let mut transaction_manager = TransactionManager::new();
+    /// transaction_manager.process_transaction(Transaction::deposit(1, 1, "2.5".parse().unwrap())).unwrap();
+    ///
+    /// assert_eq!(transaction_manager.account(1).unwrap().client, 1);
+    /// assert_eq!(transaction_manager.available(1), Some("2.5".parse().unwrap()));
+    /// assert!(transaction_manager.account(2).is_none());
+    /// assert_eq!(transaction_manager.total(2), None);
+    /// ```
+    pub fn account(&self, client: ClientId) -> Option<&ClientAccount<S>> {
+        self.client_account_index.get(&client)
+    }
+
+    /// Whether the given client has an account, i.e. any transaction that created it.
+    ///
+    /// ```
+    /// use payments_engine_rs::{Transaction, TransactionManager};
+    /// let mut transaction_manager = TransactionManager::new();
+    /// transaction_manager.process_transaction(Transaction::deposit(1, 1, "2.5".parse().unwrap())).unwrap();
+    /// assert!(transaction_manager.has_client(1));
+    /// assert!(!transaction_manager.has_client(2));
+    /// ```
+    pub fn has_client(&self, client: ClientId) -> bool {
+        self.client_account_index.contains_key(&client)
+    }
+
+    /// The client accounts in client id order.
+    pub fn accounts(&self) -> impl Iterator<Item = &ClientAccount<S>> {
+        let mut client_accounts: Vec<&ClientAccount<S>> = self.client_account_index.values().collect();
+        client_accounts.sort_by_key(|client_account| client_account.client);
+        client_accounts.into_iter()
+    }
+
+    /// The available funds of the given client.
+    pub fn available(&self, client: ClientId) -> Option<Amount> {
+        self.account(client).map(|client_account| client_account.available)
+    }
+
+    /// The held funds of the given client.
+    pub fn held(&self, client: ClientId) -> Option<Amount> {
+        self.account(client).map(|client_account| client_account.held)
+    }
+
+    /// The total funds of the given client, see [`ClientAccount::total`].
+    pub fn total(&self, client: ClientId) -> Option<Amount> {
+        self.account(client).and_then(ClientAccount::total)
+    }
+
+    /// The number of transactions processed so far, including the ignored ones.
+    pub fn processed(&self) -> u64 {
+        self.processed
+    }
+
+    /// The counts of the disputes opened, resolved and charged back so far. A partial resolve leaves
+    /// the dispute open, while a partial chargeback counts as charged back as it locks the account.
+    ///
+    /// ```
+    /// use payments_engine_rs::{DisputeStats, Transaction, TransactionManager};
+    /// let mut transaction_manager = TransactionManager::new();
+    /// transaction_manager.process_transaction(Transaction::deposit(1, 1, "2.0".parse().unwrap())).unwrap();
+    /// transaction_manager.process_transaction(Transaction::dispute(1, 1)).unwrap();
+    /// transaction_manager.process_transaction(Transaction::partial_resolve(1, 1, "0.5".parse().unwrap())).unwrap();
+    /// assert_eq!(transaction_manager.dispute_stats(), DisputeStats { opened: 1, resolved: 0, charged_back: 0 });
+    /// ```
+    pub fn dispute_stats(&self) -> DisputeStats {
+        self.dispute_stats
+    }
+
+    /// The transactions that have been ignored so far, in processing order.
+    pub fn ignored(&self) -> &[IgnoredTransaction] {
+        &self.ignored
+    }
+
+    /// The sum of the held funds of all the client accounts, saturating at [`Amount::MAX`].
+    pub fn total_held(&self) -> Amount {
+        self.client_account_index.values()
+            .fold(Amount::ZERO, |total_held, client_account| total_held.saturating_add(client_account.held))
+    }
+
+    /// Process a single transaction.
+    ///
+    /// Invalid transactions are ignored and recorded in [`TransactionManager::ignored`], except the
+    /// withdrawals exceeding the available funds with [`OverdrawPolicy::Error`], which fail.
+    pub fn process_transaction(&mut self, mut transaction: Transaction) -> Result<ProcessedOutcome, EngineError> {
+        self.processed += 1;
+        // The order in which the transactions were applied can be reconstructed from the stores.
+        transaction.set_seq(self.processed);
+        let (tx, client) = (transaction.tx, transaction.client);
+        let outcome = match self.apply_transaction(transaction) {
+            Ok(()) => match self.client_account_index.get_mut(&client) {
+                Some(client_account) => {
+                    log::trace!("Applied transaction {} of client {}", tx, client);
+                    client_account.last_tx = tx;
+                    if client_account.locked { ProcessedOutcome::Locked } else { ProcessedOutcome::Applied }
+                }
+                None => ProcessedOutcome::Applied,
+            },
+            Err(ApplyError::Ignored(reason)) => {
+                log::debug!("Ignored transaction {} of client {}: {:?}", tx, client, reason);
+                self.ignored.push(IgnoredTransaction { tx, client, reason });
+                ProcessedOutcome::Ignored(reason)
+            }
+            Err(ApplyError::Failed(err)) => return Err(err),
+        };
+        Ok(outcome)
+    }
+
+    /// Parse a single line of csv, without header, into a transaction and process it, e.g. for
+    /// scripts and tests. The line can't be parsed if it doesn't hold exactly one record.
+    ///
+    /// ```
+    /// use payments_engine_rs::{ProcessedOutcome, TransactionManager};
+    /// let mut transaction_manager = TransactionManager::new();
+    /// assert_eq!(transaction_manager.apply_str("deposit,1,1,5.0").unwrap(), ProcessedOutcome::Applied);
+    /// assert_eq!(transaction_manager.apply_str("dispute,1,1,").unwrap(), ProcessedOutcome::Applied);
+    /// assert_eq!(transaction_manager.held(1), Some("5.0".parse().unwrap()));
+    /// assert!(transaction_manager.apply_str("deposit,1,2").is_err());
+    /// ```
+    pub fn apply_str(&mut self, line: &str) -> Result<ProcessedOutcome, EngineError> {
+        self.process_transaction(csv_reader::parse_line(line)?)
+    }
+
+    /// Process all the transactions of the batch, in order, and count the applied and ignored ones.
+    ///
+    /// ```
+    /// use payments_engine_rs::{BatchReport, Transaction, TransactionManager};
+    /// let mut transaction_manager = TransactionManager::new();
+    /// let report = transaction_manager.process_batch(vec![
+    ///     Transaction::deposit(1, 1, "1.0".parse().unwrap()),
+    ///     Transaction::withdrawal(1, 2, "2.0".parse().unwrap()),
+    /// ]).unwrap();
+    /// assert_eq!(report, BatchReport { applied: 1, ignored: 1 });
+    /// ```
+    pub fn process_batch<I: IntoIterator<Item = Transaction>>(&mut self, transactions: I) -> Result<BatchReport, EngineError> {
+        let mut report = BatchReport::default();
+        for transaction in transactions {
+            match self.process_transaction(transaction)? {
+                ProcessedOutcome::Applied | ProcessedOutcome::Locked => report.applied += 1,
+                ProcessedOutcome::Ignored(_) => report.ignored += 1,
+            }
+        }
+        Ok(report)
+    }
+
+    /// Count the transaction as processed and record it as ignored for the given reason, without
+    /// applying it, e.g. for a duplicate detected by another transaction manager.
+    pub(crate) fn ignore_transaction(&mut self, transaction: Transaction, reason: IgnoreReason) -> ProcessedOutcome {
+        self.processed += 1;
+        log::debug!("Ignored transaction {} of client {}: {:?}", transaction.tx, transaction.client, reason);
+        self.ignored.push(IgnoredTransaction { tx: transaction.tx, client: transaction.client, reason });
+        ProcessedOutcome::Ignored(reason)
+    }
+
+    /// Apply a single transaction to the client account or return the reason it was ignored.
+    fn apply_transaction(&mut self, transaction: Transaction) -> Result<(), ApplyError> {
+        // The transaction ids of the deposits and withdrawals are unique across all the clients,
+        // while the disputes, resolves and chargebacks reference them. The id is used up before
+        // the other checks, so that retrying an ignored transaction with the same id is ignored
+        // too, as when replayed with the tx log or dispatched by `run_parallel`.
+        if transaction.amount().is_some() && !self.seen_tx.insert(transaction.tx) {
+            return Err(IgnoreReason::DuplicateTransaction.into());
+        }
+
+        // Zero deposits and withdrawals are ignored before they create any state.
+        if transaction.amount() == Some(Amount::ZERO) {
+            return Err(IgnoreReason::ZeroAmount.into());
+        }
+
+        // Transactions of reserved clients are ignored before they create any state.
+        if self.reserved_client_ids.contains(&transaction.client) {
+            return Err(IgnoreReason::ReservedClient.into());
+        }
+
+        // Transactions of new clients beyond the maximum are ignored before they create any state.
+        if self.max_clients.is_some_and(|max_clients| self.client_account_index.len() >= max_clients)
+            && !self.has_client(transaction.client) {
+            return Err(IgnoreReason::ClientLimitReached.into());
+        }
+
+        // Create the client if he doesn't exist and borrow it from the index.
+        let first_seen = self.client_account_index.len() as u64;
+        let client_account = self.client_account_index
+            .entry(transaction.client)
+            .or_insert_with(|| ClientAccount { first_seen, ..ClientAccount::with_store(transaction.client, self.store.for_client(transaction.client)) });
+
+        // Transactions on a locked account are ignored, with a more specific reason for the ones
+        // referencing the charged back transaction.
+        if client_account.locked {
+            if transaction.amount().is_none() && is_charged_back(client_account.transaction_index.get(transaction.tx)?) {
+                return Err(IgnoreReason::AlreadyChargedBack.into());
+            }
+            return Err(IgnoreReason::AccountLocked.into());
+        }
+
+        // Deposits and withdrawals on a frozen account are ignored.
+        if client_account.frozen && transaction.amount().is_some() {
+            return Err(IgnoreReason::AccountFrozen.into());
+        }
+
+        // Treat all the transaction types.
+        match transaction.transaction_type {
+            TransactionType::Deposit { amount } => {
+                // Neither the available nor the total funds may exceed the representable maximum.
+                let available = client_account.available.checked_add(amount)
+                    .filter(|available| available.checked_add(client_account.held).is_some())
+                    .ok_or(IgnoreReason::Overflow)?;
+                client_account.available = available;
+                client_account.tx_count += 1;
+                if !self.skip_transaction_index {
+                    client_account.transaction_index.insert(transaction)?;
+                }
+            }
+            TransactionType::Withdrawal { amount } => {
+                // The held funds are excluded from the available funds so they can't be withdrawn,
+                // and the available funds can't go below minus the credit limit. The amounts are fixed
+                // point, so withdrawing exactly the available funds leaves exactly zero.
+                let available = client_account.available.checked_sub(amount)
+                    .ok_or(IgnoreReason::Overflow)?;
+                if available.saturating_add(client_account.credit_limit) < Amount::ZERO {
+                    return match self.overdraw_policy {
+                        OverdrawPolicy::Ignore => Err(IgnoreReason::InsufficientFunds.into()),
+                        OverdrawPolicy::Error => Err(EngineError::Overdraw { client: transaction.client, tx: transaction.tx }.into()),
+                    };
+                }
+                client_account.available = available;
+                client_account.tx_count += 1;
+                if !self.skip_transaction_index {
+                    client_account.transaction_index.insert(transaction)?;
+                }
+            }
+            TransactionType::CreditLimit { amount } => {
+                client_account.credit_limit = amount;
+            }
+            TransactionType::Freeze => {
+                client_account.frozen = true;
+            }
+            TransactionType::Thaw => {
+                client_account.frozen = false;
+            }
+            TransactionType::Dispute { amount: portion } => {
+                // The index of the client only holds its own transactions, so the transactions of
+                // the other clients are unknown.
+                let disputed_transaction = client_account.transaction_index.get(transaction.tx)?
+                    .ok_or(IgnoreReason::UnknownTransaction)?;
+                match disputed_transaction.state {
+                    TransactionState::Executed | TransactionState::Resolved => {}
+                    TransactionState::Chargedback => return Err(IgnoreReason::AlreadyChargedBack.into()),
+                    TransactionState::Disputed { .. } => return Err(IgnoreReason::NotDisputable.into()),
+                }
+                if self.dispute_window.is_some_and(|dispute_window| transaction.seq.saturating_sub(disputed_transaction.seq) > dispute_window) {
+                    return Err(IgnoreReason::DisputeWindowExpired.into());
+                }
+                let amount = disputed_transaction.amount().ok_or(IgnoreReason::NotDisputable)?;
+                let amount = disputed_portion(portion, amount)?;
+                match disputed_transaction.transaction_type {
+                    TransactionType::Deposit { .. } => {
+                        let held = hold(client_account.held, amount)?;
+                        client_account.available = client_account.available.checked_sub(amount)
+                            .ok_or(IgnoreReason::Overflow)?;
+                        client_account.held = held;
+                    }
+                    TransactionType::Withdrawal { .. } => {
+                        // The withdrawn funds are held on top of the available ones, so the total grows.
+                        let held = hold(client_account.held, amount)?;
+                        client_account.available.checked_add(held).ok_or(IgnoreReason::Overflow)?;
+                        client_account.held = held;
+                    }
+                    _ => {}
+                }
+                client_account.transaction_index.set_state(transaction.tx, TransactionState::Disputed { amount })?;
+                self.dispute_stats.opened += 1;
+            }
+            TransactionType::Resolve { amount: portion } => {
+                let disputed_transaction = client_account.transaction_index.get(transaction.tx)?
+                    .ok_or(IgnoreReason::UnknownTransaction)?;
+                let disputed = match disputed_transaction.state {
+                    TransactionState::Disputed { amount } => amount,
+                    TransactionState::Chargedback => return Err(IgnoreReason::AlreadyChargedBack.into()),
+                    TransactionState::Executed | TransactionState::Resolved => return Err(IgnoreReason::NotDisputed.into()),
+                };
+                let amount = disputed_portion(portion, disputed)?;
+                let released = self.on_hold.as_ref().map_or(amount, |on_hold| on_hold(amount));
+                match disputed_transaction.transaction_type {
+                    TransactionType::Deposit { .. } => {
+                        client_account.available = client_account.available.checked_add(released)
+                            .ok_or(IgnoreReason::Overflow)?;
+                        client_account.held = release(client_account.held, amount);
+                    }
+                    TransactionType::Withdrawal { .. } => {
+                        // Only the adjustment applies, as the held funds were never taken from the available ones.
+                        client_account.available = released.checked_sub(amount)
+                            .and_then(|adjustment| client_account.available.checked_add(adjustment))
+                            .ok_or(IgnoreReason::Overflow)?;
+                        client_account.held = release(client_account.held, amount);
+                    }
+                    _ => {}
+                }
+                // The remainder of a partial resolve stays disputed.
+                let state = match disputed.checked_sub(amount) {
+                    Some(remainder) if remainder > Amount::ZERO => TransactionState::Disputed { amount: remainder },
+                    _ => TransactionState::Resolved,
+                };
+                if matches!(state, TransactionState::Resolved) {
+                    self.dispute_stats.resolved += 1;
+                }
+                client_account.transaction_index.set_state(transaction.tx, state)?;
+            }
+            TransactionType::Chargeback { amount: portion } => {
+                let disputed_transaction = client_account.transaction_index.get(transaction.tx)?
+                    .ok_or(IgnoreReason::UnknownTransaction)?;
+                let disputed = match disputed_transaction.state {
+                    TransactionState::Disputed { amount } => amount,
+                    TransactionState::Chargedback => return Err(IgnoreReason::AlreadyChargedBack.into()),
+                    TransactionState::Executed | TransactionState::Resolved => return Err(IgnoreReason::NotDisputed.into()),
+                };
+                let amount = disputed_portion(portion, disputed)?;
+                match disputed_transaction.transaction_type {
+                    // The available funds may stay negative: the client owes the charged back amount.
+                    TransactionType::Deposit { .. } => {
+                        client_account.held = release(client_account.held, amount);
+                        client_account.locked = true;
+                        client_account.locked_by = Some(transaction.tx);
+                    }
+                    TransactionType::Withdrawal { .. } => {
+                        client_account.available = client_account.available.checked_add(amount)
+                            .ok_or(IgnoreReason::Overflow)?;
+                        client_account.held = release(client_account.held, amount);
+                        client_account.locked = true;
+                        client_account.locked_by = Some(transaction.tx);
+                    }
+                    _ => {}
+                }
+                match disputed.checked_sub(amount) {
+                    // The remainder of a partial chargeback stays disputed, and held as the account is locked.
+                    Some(remainder) if remainder > Amount::ZERO => {
+                        client_account.transaction_index.set_state(transaction.tx, TransactionState::Disputed { amount: remainder })?;
+                    }
+                    _ => client_account.transaction_index.set_state(transaction.tx, TransactionState::Chargedback)?,
+                }
+                self.dispute_stats.charged_back += 1;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The reasons for which a transaction can't be applied.
+enum ApplyError {
+    /// The transaction is ignored.
+    Ignored(IgnoreReason),
+    /// The transaction failed, e.g. the store of the transactions.
+    Failed(EngineError),
+}
+
+impl From<IgnoreReason> for ApplyError {
+    fn from(reason: IgnoreReason) -> ApplyError {
+        ApplyError::Ignored(reason)
+    }
+}
+
+impl From<EngineError> for ApplyError {
+    fn from(err: EngineError) -> ApplyError {
+        ApplyError::Failed(err)
+    }
+}
+
+/// The portion of the disputed amount to which a dispute, resolve or chargeback applies, the whole
+/// disputed amount by default.
+fn disputed_portion(portion: Option<Amount>, disputed: Amount) -> Result<Amount, IgnoreReason> {
+    match portion {
+        None => Ok(disputed),
+        Some(portion) if portion == Amount::ZERO => Err(IgnoreReason::ZeroAmount),
+        Some(portion) if portion > disputed => Err(IgnoreReason::ExcessiveAmount),
+        Some(portion) => Ok(portion),
+    }
+}
+
+/// Whether the referenced transaction is charged back.
+fn is_charged_back(transaction: Option<Transaction>) -> bool {
+    matches!(transaction, Some(Transaction { state: TransactionState::Chargedback, .. }))
+}
+
+/// Hold the given amount on top of the held funds.
+fn hold(held: Amount, amount: Amount) -> Result<Amount, IgnoreReason> {
+    let held = held.checked_add(amount).ok_or(IgnoreReason::Overflow)?;
+    debug_assert!(held >= Amount::ZERO);
+    Ok(held)
+}
+
+/// Release the given amount from the held funds, which saturate at zero.
+fn release(held: Amount, amount: Amount) -> Amount {
+    let held = held.saturating_sub(amount).max(Amount::ZERO);
+    debug_assert!(held >= Amount::ZERO);
+    held
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::{Transaction, TransactionManager};
+    use crate::amount::Amount;
+    use crate::error::EngineError;
+    use crate::transaction_manager::{AccountSummary, BatchReport, ClientAccount, ClientId, IgnoreReason, IgnoredTransaction, ProcessedOutcome, TransactionState, TransactionType};
+
+    fn amount(amount: &str) -> Amount {
+        amount.parse().unwrap()
+    }
+
+    #[test]
+    fn transaction_constructors() {
+        let amount_3 = amount("3.0");
+        let transaction = Transaction::deposit(1, 2, amount("3.0"));
+        assert!(matches!(transaction.transaction_type(), TransactionType::Deposit { amount } if *amount == amount_3));
+        assert_eq!(transaction.client(), 1);
+        assert_eq!(transaction.tx(), 2);
+        assert!(matches!(transaction.state(), TransactionState::Executed));
+        assert!(matches!(Transaction::withdrawal(1, 2, amount("3.0")).transaction_type(), TransactionType::Withdrawal { amount } if *amount == amount_3));
+        assert!(matches!(Transaction::dispute(1, 2).transaction_type(), TransactionType::Dispute { amount: None }));
+        assert!(matches!(Transaction::resolve(1, 2).transaction_type(), TransactionType::Resolve { amount: None }));
+        assert!(matches!(Transaction::chargeback(1, 2).transaction_type(), TransactionType::Chargeback { amount: None }));
+    }
+
+    #[test]
+    fn transaction_amount() {
+        assert_eq!(Transaction::deposit(1, 1, amount("1.5")).amount(), Some(amount("1.5")));
+        assert_eq!(Transaction::withdrawal(1, 2, amount("2.5")).amount(), Some(amount("2.5")));
+        assert_eq!(Transaction::dispute(1, 1).amount(), None);
+        assert_eq!(Transaction::resolve(1, 1).amount(), None);
+        assert_eq!(Transaction::chargeback(1, 1).amount(), None);
+    }
+
+    #[test]
+    fn deposit() {
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Deposit { amount: amount("10.0") }, 1, 1)
+        ).unwrap();
+        assert_eq!(transaction_manager.client_account_index.len(), 1);
+        let client_account = transaction_manager.client_account_index.get(&1).unwrap();
+        assert!(!client_account.locked);
+        assert_eq!(client_account.available, amount("10.0"));
+        assert_eq!(client_account.held, amount("0.0"));
+        assert_eq!(client_account.transaction_index.len(), 1);
+        assert_eq!(client_account.client, 1);
+    }
+
+    #[test]
+    fn deposit_overflow_is_ignored() {
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Deposit { amount: Amount::MAX }, 1, 1)
+        ).unwrap();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Deposit { amount: Amount::MAX }, 1, 2)
+        ).unwrap();
+        let client_account = transaction_manager.client_account_index.get(&1).unwrap();
+        assert_eq!(client_account.available, Amount::MAX);
+        assert_eq!(client_account.transaction_index.len(), 1);
+        assert_eq!(transaction_manager.ignored(), &[
+            IgnoredTransaction { tx: 2, client: 1, reason: IgnoreReason::Overflow },
+        ]);
+    }
+
+    #[test]
+    fn zero_deposit_and_withdrawal_are_ignored() {
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.process_transaction(Transaction::deposit(1, 1, Amount::ZERO)).unwrap();
+        transaction_manager.process_transaction(Transaction::withdrawal(2, 2, Amount::ZERO)).unwrap();
+        assert!(transaction_manager.client_account_index.is_empty());
+        assert_eq!(transaction_manager.ignored(), &[
+            IgnoredTransaction { tx: 1, client: 1, reason: IgnoreReason::ZeroAmount },
+            IgnoredTransaction { tx: 2, client: 2, reason: IgnoreReason::ZeroAmount },
+        ]);
+    }
+
+    #[test]
+    fn zero_deposit_on_existing_account_is_ignored() {
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.process_transaction(Transaction::deposit(1, 1, amount("1.0"))).unwrap();
+        transaction_manager.process_transaction(Transaction::deposit(1, 2, Amount::ZERO)).unwrap();
+        transaction_manager.process_transaction(Transaction::dispute(1, 2)).unwrap();
+        let client_account = transaction_manager.client_account_index.get(&1).unwrap();
+        assert_eq!(client_account.available, amount("1.0"));
+        assert_eq!(client_account.tx_count, 1);
+        assert_eq!(client_account.transaction_index.len(), 1);
+        assert_eq!(transaction_manager.ignored(), &[
+            IgnoredTransaction { tx: 2, client: 1, reason: IgnoreReason::ZeroAmount },
+            IgnoredTransaction { tx: 2, client: 1, reason: IgnoreReason::UnknownTransaction },
+        ]);
+    }
+
+    #[test]
+    fn total_is_consistent_across_dispute_lifecycle() {
+        let mut transaction_manager = TransactionManager::new();
+        let transactions = [
+            Transaction::deposit(1, 1, amount("10.5")),
+            Transaction::withdrawal(1, 2, amount("2.25")),
+            Transaction::dispute(1, 1),
+            Transaction::resolve(1, 1),
+            Transaction::dispute(1, 2),
+            Transaction::resolve(1, 2),
+            Transaction::dispute(1, 1),
+            Transaction::chargeback(1, 1),
+        ];
+        for transaction in transactions {
+            transaction_manager.process_transaction(transaction).unwrap();
+            let client_account = transaction_manager.client_account_index.get(&1).unwrap();
+            assert_eq!(client_account.total(), client_account.available.checked_add(client_account.held));
+            assert!(client_account.total().is_some());
+        }
+        assert!(transaction_manager.ignored().is_empty());
+    }
+
+    #[test]
+    fn dispute_withdrawal_overflowing_total_is_ignored() {
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.process_transaction(Transaction::deposit(1, 1, Amount::MAX)).unwrap();
+        transaction_manager.process_transaction(Transaction::withdrawal(1, 2, amount("1.0"))).unwrap();
+        transaction_manager.process_transaction(Transaction::deposit(1, 3, amount("1.0"))).unwrap();
+        transaction_manager.process_transaction(Transaction::dispute(1, 2)).unwrap();
+        let client_account = transaction_manager.client_account_index.get(&1).unwrap();
+        assert_eq!(client_account.held, Amount::ZERO);
+        assert_eq!(client_account.total(), Some(Amount::MAX));
+        assert_eq!(transaction_manager.ignored(), &[
+            IgnoredTransaction { tx: 2, client: 1, reason: IgnoreReason::Overflow },
+        ]);
+    }
+
+    #[test]
+    fn total_held_of_all_clients() {
+        let mut transaction_manager = TransactionManager::new();
+        assert_eq!(transaction_manager.total_held(), Amount::ZERO);
+        transaction_manager.process_transaction(Transaction::deposit(1, 1, amount("1.25"))).unwrap();
+        transaction_manager.process_transaction(Transaction::deposit(2, 2, amount("2.5"))).unwrap();
+        transaction_manager.process_transaction(Transaction::deposit(2, 3, amount("4.0"))).unwrap();
+        transaction_manager.process_transaction(Transaction::dispute(1, 1)).unwrap();
+        transaction_manager.process_transaction(Transaction::dispute(2, 2)).unwrap();
+        assert_eq!(transaction_manager.total_held(), amount("3.75"));
+        transaction_manager.process_transaction(Transaction::resolve(2, 2)).unwrap();
+        assert_eq!(transaction_manager.total_held(), amount("1.25"));
+    }
+
+    #[test]
+    fn has_client_after_its_first_transaction() {
+        let mut transaction_manager = TransactionManager::new()
+            .with_reserved_client_ids(HashSet::from([3]));
+        assert!(!transaction_manager.has_client(1));
+        transaction_manager.process_transaction(Transaction::deposit(1, 1, amount("1.0"))).unwrap();
+        // A withdrawal exceeding the funds is ignored, but the account is created anyway.
+        transaction_manager.process_transaction(Transaction::withdrawal(2, 2, amount("1.0"))).unwrap();
+        // The zero amounts and the reserved clients are ignored before the account is created.
+        transaction_manager.process_transaction(Transaction::deposit(3, 3, amount("1.0"))).unwrap();
+        transaction_manager.process_transaction(Transaction::deposit(4, 4, Amount::ZERO)).unwrap();
+        assert!(transaction_manager.has_client(1));
+        assert!(transaction_manager.has_client(2));
+        assert!(!transaction_manager.has_client(3));
+        assert!(!transaction_manager.has_client(4));
+    }
+
+    #[test]
+    fn accounts_in_client_id_order() {
+        let mut transaction_manager = TransactionManager::new();
+        assert_eq!(transaction_manager.accounts().count(), 0);
+        for (client, tx) in [(7, 1), (2, 2), (300, 3), (1, 4), (2, 5)] {
+            transaction_manager.process_transaction(Transaction::deposit(client, tx, amount("1.0"))).unwrap();
+        }
+        let clients: Vec<ClientId> = transaction_manager.accounts().map(|client_account| client_account.client).collect();
+        assert_eq!(clients, vec![1, 2, 7, 300]);
+    }
+
+    #[test]
+    fn query_client_balances() {
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.process_transaction(Transaction::deposit(1, 1, amount("3.0"))).unwrap();
+        transaction_manager.process_transaction(Transaction::deposit(1, 2, amount("1.5"))).unwrap();
+        transaction_manager.process_transaction(Transaction::dispute(1, 2)).unwrap();
+        assert_eq!(transaction_manager.available(1), Some(amount("3.0")));
+        assert_eq!(transaction_manager.held(1), Some(amount("1.5")));
+        assert_eq!(transaction_manager.total(1), Some(amount("4.5")));
+        assert!(transaction_manager.account(2).is_none());
+        assert_eq!(transaction_manager.available(2), None);
+        assert_eq!(transaction_manager.held(2), None);
+    }
+
+    #[test]
+    fn reserved_client_is_ignored() {
+        let mut transaction_manager = TransactionManager::new().with_reserved_client_ids(HashSet::from([0]));
+        transaction_manager.process_transaction(Transaction::deposit(0, 1, amount("5.0"))).unwrap();
+        transaction_manager.process_transaction(Transaction::deposit(1, 2, amount("5.0"))).unwrap();
+        assert!(transaction_manager.account(0).is_none());
+        assert_eq!(transaction_manager.available(1), Some(amount("5.0")));
+        assert_eq!(transaction_manager.ignored(), &[
+            IgnoredTransaction { tx: 1, client: 0, reason: IgnoreReason::ReservedClient },
+        ]);
+    }
+
+    #[test]
+    fn clients_first_seen_order() {
+        let mut transaction_manager = TransactionManager::new();
+        for (client, tx) in [(3, 1), (1, 2), (3, 3), (2, 4)] {
+            transaction_manager.process_transaction(
+                Transaction::new(TransactionType::Deposit { amount: amount("10.0") }, client, tx)
+            ).unwrap();
+        }
+        assert_eq!(transaction_manager.client_account_index[&3].first_seen, 0);
+        assert_eq!(transaction_manager.client_account_index[&1].first_seen, 1);
+        assert_eq!(transaction_manager.client_account_index[&2].first_seen, 2);
+    }
+
+    #[test]
+    fn withdraw() {
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Deposit { amount: amount("10.0") }, 1, 1)
+        ).unwrap();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Withdrawal { amount: amount("10.0") }, 1, 2)
+        ).unwrap();
+        assert_eq!(transaction_manager.client_account_index.len(), 1);
+        let client_account = transaction_manager.client_account_index.get(&1).unwrap();
+        assert!(!client_account.locked);
+        assert_eq!(client_account.available, amount("0.0"));
+        assert_eq!(client_account.held, amount("0.0"));
+        assert_eq!(client_account.transaction_index.len(), 2);
+        assert_eq!(client_account.client, 1);
+    }
+
+    #[test]
+    fn apply_str_of_each_transaction_type() {
+        let mut transaction_manager = TransactionManager::new();
+        assert_eq!(transaction_manager.apply_str("deposit,1,1,5.0").unwrap(), ProcessedOutcome::Applied);
+        assert_eq!(transaction_manager.apply_str("withdrawal, 1, 2, 1.5").unwrap(), ProcessedOutcome::Applied);
+        assert_eq!(transaction_manager.available(1), Some(amount("3.5")));
+        assert_eq!(transaction_manager.apply_str("dispute,1,1,").unwrap(), ProcessedOutcome::Applied);
+        assert_eq!(transaction_manager.held(1), Some(amount("5.0")));
+        assert_eq!(transaction_manager.apply_str("resolve,1,1,").unwrap(), ProcessedOutcome::Applied);
+        assert_eq!(transaction_manager.held(1), Some(Amount::ZERO));
+        assert_eq!(transaction_manager.apply_str("dispute,1,1,2.0").unwrap(), ProcessedOutcome::Applied);
+        assert_eq!(transaction_manager.held(1), Some(amount("2.0")));
+        assert_eq!(transaction_manager.apply_str("chargeback,1,1,").unwrap(), ProcessedOutcome::Locked);
+        assert_eq!(transaction_manager.apply_str("credit_limit,2,3,10.0").unwrap(), ProcessedOutcome::Applied);
+        assert_eq!(transaction_manager.account(2).unwrap().credit_limit, amount("10.0"));
+        assert_eq!(transaction_manager.apply_str("freeze,2,4,").unwrap(), ProcessedOutcome::Applied);
+        assert!(transaction_manager.account(2).unwrap().frozen);
+        assert_eq!(transaction_manager.apply_str("thaw,2,5").unwrap(), ProcessedOutcome::Applied);
+        assert!(!transaction_manager.account(2).unwrap().frozen);
+        assert_eq!(transaction_manager.processed(), 9);
+    }
+
+    #[test]
+    fn apply_str_of_invalid_line_fails() {
+        let mut transaction_manager = TransactionManager::new();
+        for line in ["", "deposit,1,1,-5.0", "foobar,1,1,5.0", "deposit,1,1,5.0\ndeposit,1,2,5.0"] {
+            let err = transaction_manager.apply_str(line).unwrap_err();
+            assert!(matches!(err, EngineError::CsvParse { .. }), "{}", err);
+        }
+        assert_eq!(
+            transaction_manager.apply_str("").unwrap_err().to_string(),
+            "Error at line 1: Missing record in the line (record: )",
+        );
+        assert_eq!(transaction_manager.processed(), 0);
+    }
+
+    #[test]
+    fn withdraw_exact_available_funds() {
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.process_transaction(Transaction::deposit(1, 1, amount("1.2345"))).unwrap();
+        assert_eq!(
+            transaction_manager.process_transaction(Transaction::withdrawal(1, 2, amount("1.2345"))).unwrap(),
+            ProcessedOutcome::Applied,
+        );
+        assert_eq!(transaction_manager.available(1), Some(Amount::ZERO));
+        assert_eq!(
+            transaction_manager.process_transaction(Transaction::withdrawal(1, 3, amount("0.00000001"))).unwrap(),
+            ProcessedOutcome::Ignored(IgnoreReason::InsufficientFunds),
+        );
+
+        // The sums of amounts that aren't exact in binary floating point are exact.
+        transaction_manager.process_transaction(Transaction::deposit(2, 4, amount("0.1"))).unwrap();
+        transaction_manager.process_transaction(Transaction::deposit(2, 5, amount("0.2"))).unwrap();
+        assert_eq!(
+            transaction_manager.process_transaction(Transaction::withdrawal(2, 6, amount("0.3"))).unwrap(),
+            ProcessedOutcome::Applied,
+        );
+        assert_eq!(transaction_manager.available(2), Some(Amount::ZERO));
+    }
+
+    #[test]
+    fn withdraw_within_credit_limit() {
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.process_transaction(Transaction::credit_limit(1, 1, amount("5.0"))).unwrap();
+        transaction_manager.process_transaction(Transaction::deposit(1, 2, amount("10.0"))).unwrap();
+        assert_eq!(
+            transaction_manager.process_transaction(Transaction::withdrawal(1, 3, amount("13.0"))).unwrap(),
+            ProcessedOutcome::Applied,
+        );
+        assert_eq!(transaction_manager.available(1), Some(amount("-3.0")));
+        assert_eq!(
+            transaction_manager.process_transaction(Transaction::withdrawal(1, 4, amount("20.0"))).unwrap(),
+            ProcessedOutcome::Ignored(IgnoreReason::InsufficientFunds),
+        );
+        assert_eq!(
+            transaction_manager.process_transaction(Transaction::withdrawal(1, 5, amount("2.5"))).unwrap(),
+            ProcessedOutcome::Ignored(IgnoreReason::InsufficientFunds),
+        );
+        transaction_manager.process_transaction(Transaction::withdrawal(1, 6, amount("2.0"))).unwrap();
+        assert_eq!(transaction_manager.available(1), Some(amount("-5.0")));
+        assert_eq!(transaction_manager.client_account_index[&1].tx_count, 3);
+    }
+
+    #[test]
+    fn disputes_not_expected() {
+        let mut transaction_manager = TransactionManager::new().with_disputes_expected(false);
+        transaction_manager.process_transaction(Transaction::deposit(1, 1, amount("10.0"))).unwrap();
+        transaction_manager.process_transaction(Transaction::withdrawal(1, 2, amount("4.0"))).unwrap();
+        transaction_manager.process_transaction(Transaction::deposit(2, 3, amount("1.5"))).unwrap();
+        assert_eq!(transaction_manager.available(1), Some(amount("6.0")));
+        assert_eq!(transaction_manager.available(2), Some(amount("1.5")));
+        assert!(transaction_manager.client_account_index.values().all(|client_account| client_account.transaction_index.is_empty()));
+        assert_eq!(
+            transaction_manager.process_transaction(Transaction::dispute(1, 1)).unwrap(),
+            ProcessedOutcome::Ignored(IgnoreReason::UnknownTransaction),
+        );
+    }
+
+    #[test]
+    fn last_applied_transaction() {
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.process_transaction(Transaction::deposit(1, 3, amount("10.0"))).unwrap();
+        transaction_manager.process_transaction(Transaction::deposit(1, 1, amount("5.0"))).unwrap();
+        transaction_manager.process_transaction(Transaction::dispute(1, 3)).unwrap();
+        transaction_manager.process_transaction(Transaction::deposit(2, 2, amount("1.0"))).unwrap();
+        // Ignored transactions don't count.
+        transaction_manager.process_transaction(Transaction::withdrawal(1, 4, amount("100.0"))).unwrap();
+        transaction_manager.process_transaction(Transaction::resolve(1, 5)).unwrap();
+        assert_eq!(transaction_manager.client_account_index[&1].last_tx, 3);
+        assert_eq!(transaction_manager.client_account_index[&2].last_tx, 2);
+    }
+
+    #[test]
+    fn merge_transaction_managers() {
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.process_transaction(Transaction::deposit(1, 1, amount("10.0"))).unwrap();
+        transaction_manager.process_transaction(Transaction::deposit(2, 2, amount("3.0"))).unwrap();
+        transaction_manager.process_transaction(Transaction::dispute(2, 2)).unwrap();
+        transaction_manager.process_transaction(Transaction::chargeback(2, 2)).unwrap();
+        transaction_manager.process_transaction(Transaction::deposit(4, 8, amount("1.0"))).unwrap();
+        let mut other = TransactionManager::new();
+        other.process_transaction(Transaction::deposit(3, 3, amount("1.0"))).unwrap();
+        other.process_transaction(Transaction::deposit(1, 4, amount("5.0"))).unwrap();
+        other.process_transaction(Transaction::withdrawal(1, 5, amount("2.0"))).unwrap();
+        other.process_transaction(Transaction::withdrawal(1, 6, amount("20.0"))).unwrap();
+        other.process_transaction(Transaction::deposit(2, 7, amount("1.0"))).unwrap();
+        other.process_transaction(Transaction::withdrawal(4, 9, amount("2.0"))).unwrap();
+        assert!(other.has_client(4));
+
+        transaction_manager.merge(other).unwrap();
+        assert_eq!(transaction_manager.processed(), 11);
+        assert_eq!(transaction_manager.ignored().len(), 2);
+        let client_account = &transaction_manager.client_account_index[&1];
+        assert_eq!(client_account.available, amount("13.0"));
+        assert_eq!(client_account.transaction_index.len(), 3);
+        assert_eq!(client_account.tx_count, 3);
+        assert_eq!(client_account.last_tx, 5);
+        let client_account = &transaction_manager.client_account_index[&2];
+        assert_eq!(client_account.available, amount("1.0"));
+        assert!(client_account.locked);
+        assert_eq!(client_account.locked_by, Some(2));
+        let client_account = &transaction_manager.client_account_index[&3];
+        assert_eq!(client_account.available, amount("1.0"));
+        assert_eq!(client_account.first_seen, 3);
+        // The last transaction of the client is kept when the other account applied none.
+        let client_account = &transaction_manager.client_account_index[&4];
+        assert_eq!(client_account.available, amount("1.0"));
+        assert_eq!(client_account.last_tx, 8);
+
+        // The transactions of both managers can be disputed, and their ids reused only once.
+        transaction_manager.process_transaction(Transaction::dispute(1, 4)).unwrap();
+        assert_eq!(transaction_manager.held(1), Some(amount("5.0")));
+        assert_eq!(
+            transaction_manager.process_transaction(Transaction::deposit(1, 3, amount("1.0"))).unwrap(),
+            ProcessedOutcome::Ignored(IgnoreReason::DuplicateTransaction),
+        );
+    }
+
+    #[test]
+    fn merge_colliding_transaction_ids_fails() {
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.process_transaction(Transaction::deposit(1, 1, amount("10.0"))).unwrap();
+        let mut other = TransactionManager::new();
+        other.process_transaction(Transaction::deposit(2, 1, amount("5.0"))).unwrap();
+        assert!(matches!(transaction_manager.merge(other), Err(EngineError::DuplicateTransaction { tx: 1 })));
+        assert_eq!(transaction_manager.client_account_index.len(), 1);
+        assert_eq!(transaction_manager.available(1), Some(amount("10.0")));
+    }
 
-#[derive(Debug)]
-struct ClientAccountLockedError();
+    #[test]
+    fn resolve_with_fee_hook() {
+        let fee = amount("0.5");
+        let mut transaction_manager = TransactionManager::new()
+            .with_on_hold(Box::new(move |amount: Amount| amount.saturating_sub(fee)));
+        transaction_manager.process_transaction(Transaction::deposit(1, 1, amount("10.0"))).unwrap();
+        transaction_manager.process_transaction(Transaction::withdrawal(1, 2, amount("4.0"))).unwrap();
+        transaction_manager.process_transaction(Transaction::dispute(1, 1)).unwrap();
+        transaction_manager.process_transaction(Transaction::resolve(1, 1)).unwrap();
+        assert_eq!(transaction_manager.available(1), Some(amount("5.5")));
+        assert_eq!(transaction_manager.held(1), Some(Amount::ZERO));
 
-impl std::error::Error for ClientAccountLockedError {}
+        transaction_manager.process_transaction(Transaction::dispute(1, 2)).unwrap();
+        transaction_manager.process_transaction(Transaction::partial_resolve(1, 2, amount("1.0"))).unwrap();
+        assert_eq!(transaction_manager.available(1), Some(amount("5.0")));
+        assert_eq!(transaction_manager.held(1), Some(amount("3.0")));
 
-impl fmt::Display for ClientAccountLockedError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "The client account is locked")
+        // The chargebacks aren't adjusted.
+        transaction_manager.process_transaction(Transaction::chargeback(1, 2)).unwrap();
+        assert_eq!(transaction_manager.available(1), Some(amount("8.0")));
     }
-}
 
-/// Processor for transactions and the generation of the client account index.
-pub struct TransactionManager
-{
-    pub client_account_index: HashMap<u16, ClientAccount>,
-}
+    #[test]
+    fn freeze_and_thaw() {
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.process_transaction(Transaction::deposit(1, 1, amount("10.0"))).unwrap();
+        transaction_manager.process_transaction(Transaction::freeze(1, 2)).unwrap();
+        assert_eq!(
+            transaction_manager.process_transaction(Transaction::deposit(1, 3, amount("5.0"))).unwrap(),
+            ProcessedOutcome::Ignored(IgnoreReason::AccountFrozen),
+        );
+        assert_eq!(
+            transaction_manager.process_transaction(Transaction::withdrawal(1, 4, amount("5.0"))).unwrap(),
+            ProcessedOutcome::Ignored(IgnoreReason::AccountFrozen),
+        );
+        // The disputes of a frozen account are still settled.
+        transaction_manager.process_transaction(Transaction::dispute(1, 1)).unwrap();
+        assert_eq!(transaction_manager.held(1), Some(amount("10.0")));
+        transaction_manager.process_transaction(Transaction::resolve(1, 1)).unwrap();
+        transaction_manager.process_transaction(Transaction::thaw(1, 5)).unwrap();
+        assert_eq!(
+            transaction_manager.process_transaction(Transaction::deposit(1, 6, amount("5.0"))).unwrap(),
+            ProcessedOutcome::Applied,
+        );
+        assert_eq!(transaction_manager.available(1), Some(amount("15.0")));
+        assert!(!transaction_manager.client_account_index[&1].locked);
+    }
 
-impl TransactionManager {
-    pub fn new() -> TransactionManager {
-        TransactionManager {
-            client_account_index: HashMap::new()
-        }
+    #[test]
+    fn thaw_charged_back_account_is_ignored() {
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.process_transaction(Transaction::deposit(1, 1, amount("10.0"))).unwrap();
+        transaction_manager.process_transaction(Transaction::dispute(1, 1)).unwrap();
+        transaction_manager.process_transaction(Transaction::chargeback(1, 1)).unwrap();
+        assert_eq!(
+            transaction_manager.process_transaction(Transaction::thaw(1, 2)).unwrap(),
+            ProcessedOutcome::Ignored(IgnoreReason::AccountLocked),
+        );
+        assert!(transaction_manager.client_account_index[&1].locked);
     }
 
-    /// Process a single transaction.
-    pub fn process_transaction(&mut self, transaction: Transaction) -> Result<(), Box<dyn Error>> {
-        // Create the client if he doesn't exist.
-        if !self.client_account_index.contains_key(&transaction.client) {
-            self.client_account_index.insert(transaction.client, ClientAccount::new(transaction.client.clone(), 0.0, 0.0));
-        }
+    /// Logger capturing the messages of each test, logged from the thread running it.
+    struct CapturingLogger;
 
-        // Borrow the client from the index.
-        let mut client_account = self.client_account_index
-            .get_mut(&transaction.client).unwrap(); // Should never panic
+    thread_local! {
+        static LOGGED: std::cell::RefCell<Vec<String>> = const { std::cell::RefCell::new(Vec::new()) };
+    }
 
-        if client_account.locked {
-            return Err(Box::new(ClientAccountLockedError()));
+    impl log::Log for CapturingLogger {
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            metadata.level() <= log::Level::Debug
         }
 
-        // Treat all the transaction types.
-        match transaction.transaction_type {
-            TransactionType::Deposit { amount } => {
-                client_account.available += amount;
-                client_account.transaction_index.insert(transaction.tx, transaction);
-            }
-            TransactionType::Withdrawal { amount } => {
-                if client_account.available >= amount {
-                    client_account.available -= amount;
-                    client_account.transaction_index.insert(transaction.tx, transaction);
-                }
-            }
-            TransactionType::Dispute => {
-                // What if we dispute an invalid element or a deposit on a locked account?
-                if let Some(disputed_transaction) = client_account.transaction_index.get_mut(&transaction.tx) {
-                    if let TransactionState::Executed = disputed_transaction.state {
-                        match disputed_transaction.transaction_type {
-                            TransactionType::Deposit { amount } => {
-                                client_account.held += amount;
-                                client_account.available -= amount;
-                                disputed_transaction.state = TransactionState::Disputed;
-                            }
-                            _ => {}
-                        }
-                    }
-                }
-            }
-            TransactionType::Resolve => {
-                if let Some(disputed_transaction) = client_account.transaction_index.get_mut(&transaction.tx) {
-                    if let TransactionState::Disputed = disputed_transaction.state {
-                        match disputed_transaction.transaction_type {
-                            TransactionType::Deposit { amount } => {
-                                client_account.held -= amount;
-                                client_account.available += amount;
-                                disputed_transaction.state = TransactionState::Resolved;
-                            }
-                            _ => {}
-                        }
-                    }
-                }
-            }
-            TransactionType::Chargeback => {
-                if let Some(disputed_transaction) = client_account.transaction_index.get_mut(&transaction.tx) {
-                    if let TransactionState::Disputed = disputed_transaction.state {
-                        match disputed_transaction.transaction_type {
-                            TransactionType::Deposit { amount } => {
-                                client_account.held -= amount;
-                                client_account.locked = true;
-                                disputed_transaction.state = TransactionState::Chargedback;
-                            }
-                            _ => {}
-                        }
-                    }
-                }
+        fn log(&self, record: &log::Record) {
+            if self.enabled(record.metadata()) {
+                LOGGED.with(|logged| logged.borrow_mut().push(record.args().to_string()));
             }
         }
 
-        Ok(())
+        fn flush(&self) {}
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::{Transaction, TransactionManager};
-    use crate::transaction_manager::TransactionType;
+    /// Capture the messages logged by the current thread from now on.
+    fn capture_logs() {
+        static INIT: std::sync::Once = std::sync::Once::new();
+        INIT.call_once(|| {
+            // Another logger may already be installed, then nothing is captured.
+            let _ = log::set_logger(&CapturingLogger);
+            log::set_max_level(log::LevelFilter::Debug);
+        });
+        LOGGED.with(|logged| logged.borrow_mut().clear());
+    }
 
     #[test]
-    fn deposit() {
+    fn withdraw_too_much_is_logged() {
+        capture_logs();
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.process_transaction(Transaction::deposit(42, 4241, amount("1.0"))).unwrap();
+        transaction_manager.process_transaction(Transaction::withdrawal(42, 4242, amount("2.0"))).unwrap();
+        LOGGED.with(|logged| assert_eq!(*logged.borrow(), ["Ignored transaction 4242 of client 42: InsufficientFunds"]));
+    }
+
+    #[test]
+    fn withdraw_too_much_is_ignored() {
         let mut transaction_manager = TransactionManager::new();
         transaction_manager.process_transaction(
-            Transaction::new(TransactionType::Deposit { amount: 10.0 }, 1, 1)
+            Transaction::new(TransactionType::Deposit { amount: amount("10.0") }, 1, 1)
+        ).unwrap();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Withdrawal { amount: amount("20.0") }, 1, 2)
         ).unwrap();
         assert_eq!(transaction_manager.client_account_index.len(), 1);
         let client_account = transaction_manager.client_account_index.get(&1).unwrap();
-        assert_eq!(client_account.locked, false);
-        assert_eq!(client_account.available, 10.);
-        assert_eq!(client_account.held, 0.);
+        assert!(!client_account.locked);
+        assert_eq!(client_account.available, amount("10.0"));
+        assert_eq!(client_account.held, amount("0.0"));
         assert_eq!(client_account.transaction_index.len(), 1);
         assert_eq!(client_account.client, 1);
+        assert_eq!(transaction_manager.ignored(), &[
+            IgnoredTransaction { tx: 2, client: 1, reason: IgnoreReason::InsufficientFunds },
+        ]);
     }
 
     #[test]
-    fn withdraw() {
+    fn dispute_unknown_tx_is_ignored() {
         let mut transaction_manager = TransactionManager::new();
         transaction_manager.process_transaction(
-            Transaction::new(TransactionType::Deposit { amount: 10.0 }, 1, 1)
+            Transaction::new(TransactionType::Deposit { amount: amount("10.0") }, 1, 1)
         ).unwrap();
         transaction_manager.process_transaction(
-            Transaction::new(TransactionType::Withdrawal { amount: 10.0 }, 1, 2)
+            Transaction::new(TransactionType::Dispute { amount: None }, 1, 2)
         ).unwrap();
-        assert_eq!(transaction_manager.client_account_index.len(), 1);
         let client_account = transaction_manager.client_account_index.get(&1).unwrap();
-        assert_eq!(client_account.locked, false);
-        assert_eq!(client_account.available, 0.);
-        assert_eq!(client_account.held, 0.);
-        assert_eq!(client_account.transaction_index.len(), 2);
-        assert_eq!(client_account.client, 1);
+        assert_eq!(client_account.available, amount("10.0"));
+        assert_eq!(client_account.held, amount("0.0"));
+        assert_eq!(transaction_manager.ignored(), &[
+            IgnoredTransaction { tx: 2, client: 1, reason: IgnoreReason::UnknownTransaction },
+        ]);
+        assert_eq!(transaction_manager.processed(), 2);
     }
 
     #[test]
-    fn withdraw_too_much_is_ignored() {
+    fn dispute() {
         let mut transaction_manager = TransactionManager::new();
         transaction_manager.process_transaction(
-            Transaction::new(TransactionType::Deposit { amount: 10.0 }, 1, 1)
+            Transaction::new(TransactionType::Deposit { amount: amount("10.0") }, 1, 1)
         ).unwrap();
         transaction_manager.process_transaction(
-            Transaction::new(TransactionType::Withdrawal { amount: 20.0 }, 1, 2)
+            Transaction::new(TransactionType::Dispute { amount: None }, 1, 1)
         ).unwrap();
         assert_eq!(transaction_manager.client_account_index.len(), 1);
         let client_account = transaction_manager.client_account_index.get(&1).unwrap();
-        assert_eq!(client_account.locked, false);
-        assert_eq!(client_account.available, 10.);
-        assert_eq!(client_account.held, 0.);
+        assert!(!client_account.locked);
+        assert_eq!(client_account.available, amount("0.0"));
+        assert_eq!(client_account.held, amount("10.0"));
         assert_eq!(client_account.transaction_index.len(), 1);
         assert_eq!(client_account.client, 1);
     }
 
     #[test]
-    fn dispute() {
+    fn withdraw_held_funds_is_ignored() {
         let mut transaction_manager = TransactionManager::new();
         transaction_manager.process_transaction(
-            Transaction::new(TransactionType::Deposit { amount: 10.0 }, 1, 1)
+            Transaction::new(TransactionType::Deposit { amount: amount("10.0") }, 1, 1)
         ).unwrap();
         transaction_manager.process_transaction(
-            Transaction::new(TransactionType::Dispute, 1, 1)
+            Transaction::new(TransactionType::Dispute { amount: None }, 1, 1)
+        ).unwrap();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Withdrawal { amount: amount("5.0") }, 1, 2)
         ).unwrap();
-        assert_eq!(transaction_manager.client_account_index.len(), 1);
         let client_account = transaction_manager.client_account_index.get(&1).unwrap();
-        assert_eq!(client_account.locked, false);
-        assert_eq!(client_account.available, 0.);
-        assert_eq!(client_account.held, 10.);
+        assert!(!client_account.locked);
+        assert_eq!(client_account.available, amount("0.0"));
+        assert_eq!(client_account.held, amount("10.0"));
         assert_eq!(client_account.transaction_index.len(), 1);
-        assert_eq!(client_account.client, 1);
+        assert_eq!(transaction_manager.ignored(), &[
+            IgnoredTransaction { tx: 2, client: 1, reason: IgnoreReason::InsufficientFunds },
+        ]);
+    }
+
+    #[test]
+    fn dispute_several_txs_of_same_client() {
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.process_transaction(Transaction::deposit(1, 1, amount("10.0"))).unwrap();
+        transaction_manager.process_transaction(Transaction::deposit(1, 2, amount("5.0"))).unwrap();
+        transaction_manager.process_transaction(Transaction::dispute(1, 1)).unwrap();
+        assert_eq!(transaction_manager.held(1), Some(amount("10.0")));
+        assert_eq!(transaction_manager.available(1), Some(amount("5.0")));
+        transaction_manager.process_transaction(Transaction::dispute(1, 2)).unwrap();
+        assert_eq!(transaction_manager.held(1), Some(amount("15.0")));
+        assert_eq!(transaction_manager.available(1), Some(amount("0.0")));
+        transaction_manager.process_transaction(Transaction::resolve(1, 1)).unwrap();
+        assert_eq!(transaction_manager.held(1), Some(amount("5.0")));
+        assert_eq!(transaction_manager.available(1), Some(amount("10.0")));
+        transaction_manager.process_transaction(Transaction::resolve(1, 2)).unwrap();
+        assert_eq!(transaction_manager.held(1), Some(amount("0.0")));
+        assert_eq!(transaction_manager.available(1), Some(amount("15.0")));
+        assert!(transaction_manager.ignored().is_empty());
     }
 
     #[test]
     fn dispute_twice_is_ignored() {
         let mut transaction_manager = TransactionManager::new();
         transaction_manager.process_transaction(
-            Transaction::new(TransactionType::Deposit { amount: 10.0 }, 1, 1)
+            Transaction::new(TransactionType::Deposit { amount: amount("10.0") }, 1, 1)
         ).unwrap();
         transaction_manager.process_transaction(
-            Transaction::new(TransactionType::Dispute, 1, 1)
+            Transaction::new(TransactionType::Dispute { amount: None }, 1, 1)
         ).unwrap();
         transaction_manager.process_transaction(
-            Transaction::new(TransactionType::Dispute, 1, 1)
+            Transaction::new(TransactionType::Dispute { amount: None }, 1, 1)
         ).unwrap();
         assert_eq!(transaction_manager.client_account_index.len(), 1);
         let client_account = transaction_manager.client_account_index.get(&1).unwrap();
-        assert_eq!(client_account.locked, false);
-        assert_eq!(client_account.available, 0.);
-        assert_eq!(client_account.held, 10.);
+        assert!(!client_account.locked);
+        assert_eq!(client_account.available, amount("0.0"));
+        assert_eq!(client_account.held, amount("10.0"));
         assert_eq!(client_account.transaction_index.len(), 1);
         assert_eq!(client_account.client, 1);
     }
 
+    #[test]
+    fn dispute_tx_of_other_client_is_isolated() {
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Deposit { amount: amount("10.0") }, 1, 5)
+        ).unwrap();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Deposit { amount: amount("20.0") }, 2, 6)
+        ).unwrap();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Dispute { amount: None }, 2, 5)
+        ).unwrap();
+        assert_eq!(transaction_manager.client_account_index.len(), 2);
+        let client_account = transaction_manager.client_account_index.get(&1).unwrap();
+        assert!(!client_account.locked);
+        assert_eq!(client_account.available, amount("10.0"));
+        assert_eq!(client_account.held, amount("0.0"));
+        let client_account = transaction_manager.client_account_index.get(&2).unwrap();
+        assert_eq!(client_account.available, amount("20.0"));
+        assert_eq!(client_account.held, amount("0.0"));
+        assert_eq!(transaction_manager.ignored()[0].reason, IgnoreReason::UnknownTransaction);
+    }
+
+    #[test]
+    fn reused_tx_id_of_other_client_is_ignored() {
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.process_transaction(Transaction::deposit(1, 7, amount("10.0"))).unwrap();
+        assert_eq!(
+            transaction_manager.process_transaction(Transaction::deposit(2, 7, amount("20.0"))).unwrap(),
+            ProcessedOutcome::Ignored(IgnoreReason::DuplicateTransaction),
+        );
+        assert_eq!(transaction_manager.ignored(), [IgnoredTransaction { tx: 7, client: 2, reason: IgnoreReason::DuplicateTransaction }]);
+        assert_eq!(transaction_manager.available(1), Some(amount("10.0")));
+        assert!(transaction_manager.account(2).is_none());
+
+        // The disputes reference the id of the existing transaction.
+        transaction_manager.process_transaction(Transaction::dispute(1, 7)).unwrap();
+        assert_eq!(transaction_manager.held(1), Some(amount("10.0")));
+    }
+
+    #[test]
+    fn reused_tx_id_of_ignored_transaction_is_ignored() {
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.process_transaction(Transaction::deposit(1, 1, amount("10.0"))).unwrap();
+        assert_eq!(
+            transaction_manager.process_transaction(Transaction::withdrawal(1, 2, amount("20.0"))).unwrap(),
+            ProcessedOutcome::Ignored(IgnoreReason::InsufficientFunds),
+        );
+        // The id of the ignored withdrawal is reserved, even for a valid one.
+        assert_eq!(
+            transaction_manager.process_transaction(Transaction::withdrawal(1, 2, amount("5.0"))).unwrap(),
+            ProcessedOutcome::Ignored(IgnoreReason::DuplicateTransaction),
+        );
+        assert_eq!(transaction_manager.available(1), Some(amount("10.0")));
+        let mut tx_log = Vec::new();
+        transaction_manager.save_tx_log(&mut tx_log).unwrap();
+        let tx_log: HashSet<u32> = serde_json::from_slice(&tx_log).unwrap();
+        assert_eq!(tx_log, HashSet::from([1, 2]));
+    }
+
     #[test]
     fn resolve_disputed_tx() {
         let mut transaction_manager = TransactionManager::new();
         transaction_manager.process_transaction(
-            Transaction::new(TransactionType::Deposit { amount: 10.0 }, 1, 1)
+            Transaction::new(TransactionType::Deposit { amount: amount("10.0") }, 1, 1)
         ).unwrap();
         transaction_manager.process_transaction(
-            Transaction::new(TransactionType::Dispute, 1, 1)
+            Transaction::new(TransactionType::Dispute { amount: None }, 1, 1)
         ).unwrap();
         transaction_manager.process_transaction(
-            Transaction::new(TransactionType::Resolve, 1, 1)
+            Transaction::new(TransactionType::Resolve { amount: None }, 1, 1)
         ).unwrap();
         assert_eq!(transaction_manager.client_account_index.len(), 1);
         let client_account = transaction_manager.client_account_index.get(&1).unwrap();
-        assert_eq!(client_account.locked, false);
-        assert_eq!(client_account.available, 10.);
-        assert_eq!(client_account.held, 0.);
+        assert!(!client_account.locked);
+        assert_eq!(client_account.available, amount("10.0"));
+        assert_eq!(client_account.held, amount("0.0"));
         assert_eq!(client_account.transaction_index.len(), 1);
         assert_eq!(client_account.client, 1);
     }
 
+    #[test]
+    fn dispute_resolved_tx_again() {
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Deposit { amount: amount("10.0") }, 1, 1)
+        ).unwrap();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Dispute { amount: None }, 1, 1)
+        ).unwrap();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Resolve { amount: None }, 1, 1)
+        ).unwrap();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Dispute { amount: None }, 1, 1)
+        ).unwrap();
+        let client_account = transaction_manager.client_account_index.get(&1).unwrap();
+        assert!(!client_account.locked);
+        assert_eq!(client_account.available, amount("0.0"));
+        assert_eq!(client_account.held, amount("10.0"));
+        assert!(matches!(client_account.transaction_index[&1].state(), TransactionState::Disputed { .. }));
+        assert!(transaction_manager.ignored().is_empty());
+    }
+
+    #[test]
+    fn dispute_charged_back_tx_is_ignored() {
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Deposit { amount: amount("10.0") }, 1, 1)
+        ).unwrap();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Dispute { amount: None }, 1, 1)
+        ).unwrap();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Chargeback { amount: None }, 1, 1)
+        ).unwrap();
+        let client_account = transaction_manager.client_account_index.get_mut(&1).unwrap();
+        assert!(matches!(client_account.transaction_index[&1].state(), TransactionState::Chargedback));
+        // Unlock the account to check that the chargeback is terminal.
+        client_account.locked = false;
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Dispute { amount: None }, 1, 1)
+        ).unwrap();
+        let client_account = transaction_manager.client_account_index.get(&1).unwrap();
+        assert_eq!(client_account.available, amount("0.0"));
+        assert_eq!(client_account.held, amount("0.0"));
+        assert_eq!(transaction_manager.ignored(), &[
+            IgnoredTransaction { tx: 1, client: 1, reason: IgnoreReason::AlreadyChargedBack },
+        ]);
+    }
+
+    #[test]
+    fn dispute_charged_back_tx_is_recorded() {
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.process_transaction(Transaction::deposit(1, 1, amount("10.0"))).unwrap();
+        transaction_manager.process_transaction(Transaction::dispute(1, 1)).unwrap();
+        transaction_manager.process_transaction(Transaction::chargeback(1, 1)).unwrap();
+        assert_eq!(
+            transaction_manager.process_transaction(Transaction::dispute(1, 1)).unwrap(),
+            ProcessedOutcome::Ignored(IgnoreReason::AlreadyChargedBack),
+        );
+        assert_eq!(
+            transaction_manager.process_transaction(Transaction::resolve(1, 1)).unwrap(),
+            ProcessedOutcome::Ignored(IgnoreReason::AlreadyChargedBack),
+        );
+        assert_eq!(
+            transaction_manager.process_transaction(Transaction::dispute(1, 2)).unwrap(),
+            ProcessedOutcome::Ignored(IgnoreReason::AccountLocked),
+        );
+    }
+
     #[test]
     fn resolve_undisputed_tx_is_ignored() {
         let mut transaction_manager = TransactionManager::new();
         transaction_manager.process_transaction(
-            Transaction::new(TransactionType::Deposit { amount: 10.0 }, 1, 1)
+            Transaction::new(TransactionType::Deposit { amount: amount("10.0") }, 1, 1)
         ).unwrap();
         transaction_manager.process_transaction(
-            Transaction::new(TransactionType::Resolve, 1, 1)
+            Transaction::new(TransactionType::Resolve { amount: None }, 1, 1)
         ).unwrap();
         assert_eq!(transaction_manager.client_account_index.len(), 1);
         let client_account = transaction_manager.client_account_index.get(&1).unwrap();
-        assert_eq!(client_account.locked, false);
-        assert_eq!(client_account.available, 10.);
-        assert_eq!(client_account.held, 0.);
+        assert!(!client_account.locked);
+        assert_eq!(client_account.available, amount("10.0"));
+        assert_eq!(client_account.held, amount("0.0"));
         assert_eq!(client_account.transaction_index.len(), 1);
         assert_eq!(client_account.client, 1);
     }
@@ -312,37 +1862,206 @@ mod tests {
     fn chargeback() {
         let mut transaction_manager = TransactionManager::new();
         transaction_manager.process_transaction(
-            Transaction::new(TransactionType::Deposit { amount: 10.0 }, 1, 1)
+            Transaction::new(TransactionType::Deposit { amount: amount("10.0") }, 1, 1)
         ).unwrap();
         transaction_manager.process_transaction(
-            Transaction::new(TransactionType::Dispute, 1, 1)
+            Transaction::new(TransactionType::Dispute { amount: None }, 1, 1)
         ).unwrap();
         transaction_manager.process_transaction(
-            Transaction::new(TransactionType::Chargeback, 1, 1)
+            Transaction::new(TransactionType::Chargeback { amount: None }, 1, 1)
         ).unwrap();
         assert_eq!(transaction_manager.client_account_index.len(), 1);
         let client_account = transaction_manager.client_account_index.get(&1).unwrap();
-        assert_eq!(client_account.locked, true);
-        assert_eq!(client_account.available, 0.);
-        assert_eq!(client_account.held, 0.);
+        assert!(client_account.locked);
+        assert_eq!(client_account.locked_by, Some(1));
+        assert_eq!(client_account.available, amount("0.0"));
+        assert_eq!(client_account.held, amount("0.0"));
         assert_eq!(client_account.transaction_index.len(), 1);
         assert_eq!(client_account.client, 1);
     }
 
+    #[test]
+    fn partial_chargeback_leaves_residual_held_funds() {
+        let amount_6 = amount("6.0");
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.process_transaction(Transaction::deposit(1, 1, amount("10.0"))).unwrap();
+        transaction_manager.process_transaction(Transaction::dispute(1, 1)).unwrap();
+        let outcome = transaction_manager.process_transaction(Transaction::partial_chargeback(1, 1, amount("4.0"))).unwrap();
+        assert_eq!(outcome, ProcessedOutcome::Locked);
+        let client_account = &transaction_manager.client_account_index[&1];
+        assert!(client_account.locked);
+        assert_eq!(client_account.available, amount("0.0"));
+        assert_eq!(client_account.held, amount("6.0"));
+        assert!(matches!(client_account.transaction_index[&1].state(), TransactionState::Disputed { amount } if *amount == amount_6));
+    }
+
+    #[test]
+    fn partial_dispute_and_resolve() {
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.process_transaction(Transaction::deposit(1, 1, amount("10.0"))).unwrap();
+        transaction_manager.process_transaction(Transaction::partial_dispute(1, 1, amount("6.0"))).unwrap();
+        assert_eq!(transaction_manager.available(1), Some(amount("4.0")));
+        assert_eq!(transaction_manager.held(1), Some(amount("6.0")));
+
+        // The remainder of a partial resolve stays disputed and held.
+        transaction_manager.process_transaction(Transaction::partial_resolve(1, 1, amount("2.5"))).unwrap();
+        assert_eq!(transaction_manager.available(1), Some(amount("6.5")));
+        assert_eq!(transaction_manager.held(1), Some(amount("3.5")));
+        assert!(matches!(transaction_manager.client_account_index[&1].transaction_index[&1].state(), TransactionState::Disputed { .. }));
+
+        transaction_manager.process_transaction(Transaction::resolve(1, 1)).unwrap();
+        assert_eq!(transaction_manager.available(1), Some(amount("10.0")));
+        assert_eq!(transaction_manager.held(1), Some(amount("0.0")));
+        assert!(matches!(transaction_manager.client_account_index[&1].transaction_index[&1].state(), TransactionState::Resolved));
+    }
+
+    #[test]
+    fn dispute_outside_window_ignored() {
+        let mut transaction_manager = TransactionManager::new().with_dispute_window(Some(1));
+        transaction_manager.process_transaction(Transaction::deposit(1, 1, amount("3.0"))).unwrap();
+        transaction_manager.process_transaction(Transaction::dispute(1, 1)).unwrap();
+        transaction_manager.process_transaction(Transaction::deposit(1, 2, amount("1.0"))).unwrap();
+        // The settlement of a dispute isn't limited by the window, unlike a new dispute.
+        transaction_manager.process_transaction(Transaction::resolve(1, 1)).unwrap();
+        assert_eq!(
+            transaction_manager.process_transaction(Transaction::dispute(1, 1)).unwrap(),
+            ProcessedOutcome::Ignored(IgnoreReason::DisputeWindowExpired),
+        );
+        assert_eq!(transaction_manager.ignored(), &[
+            IgnoredTransaction { tx: 1, client: 1, reason: IgnoreReason::DisputeWindowExpired },
+        ]);
+        assert_eq!(transaction_manager.available(1), Some(amount("4.0")));
+        assert_eq!(transaction_manager.held(1), Some(Amount::ZERO));
+    }
+
+    #[test]
+    fn summary_of_client_accounts() {
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.process_transaction(Transaction::deposit(1, 1, amount("3.0"))).unwrap();
+        transaction_manager.process_transaction(Transaction::deposit(2, 2, amount("1.5"))).unwrap();
+        transaction_manager.process_transaction(Transaction::dispute(2, 2)).unwrap();
+        transaction_manager.process_transaction(Transaction::chargeback(2, 2)).unwrap();
+        transaction_manager.process_transaction(Transaction::deposit(1, 3, amount("1.0"))).unwrap();
+        transaction_manager.process_transaction(Transaction::dispute(1, 3)).unwrap();
+        let summaries: Vec<AccountSummary> = transaction_manager.accounts().map(|client_account| client_account.summary().unwrap()).collect();
+        assert_eq!(summaries, [
+            AccountSummary { client: 1, available: amount("3.0"), held: amount("1.0"), total: amount("4.0"), locked: false },
+            AccountSummary { client: 2, available: Amount::ZERO, held: Amount::ZERO, total: Amount::ZERO, locked: true },
+        ]);
+        // The summary is a snapshot, unchanged by the later transactions.
+        transaction_manager.process_transaction(Transaction::resolve(1, 3)).unwrap();
+        assert_ne!(transaction_manager.account(1).unwrap().summary(), Some(summaries[0]));
+        assert_eq!(ClientAccount::new(3, Amount::MAX, amount("1.0")).summary(), None);
+    }
+
+    #[test]
+    fn compact_charged_back_transactions() {
+        let transactions = [
+            Transaction::deposit(1, 1, amount("1.0")),
+            Transaction::deposit(1, 2, amount("1.0")),
+            Transaction::deposit(1, 3, amount("1.0")),
+            Transaction::dispute(1, 1),
+            Transaction::resolve(1, 1),
+            Transaction::dispute(1, 2),
+            Transaction::deposit(2, 5, amount("2.0")),
+            Transaction::dispute(2, 5),
+            Transaction::chargeback(2, 5),
+        ];
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.process_batch(transactions.clone()).unwrap();
+        let mut compacted = TransactionManager::new();
+        compacted.process_batch(transactions).unwrap();
+
+        compacted.compact();
+        let mut index: Vec<u32> = compacted.client_account_index[&1].transaction_index.keys().copied().collect();
+        index.sort();
+        assert_eq!(index, [1, 2, 3]);
+        assert!(compacted.client_account_index[&2].transaction_index.is_empty());
+        assert!(compacted.accounts().map(ClientAccount::summary).eq(transaction_manager.accounts().map(ClientAccount::summary)));
+
+        // The resolved transactions can be disputed again, and the later results are unchanged.
+        let transactions = [
+            Transaction::dispute(1, 1),
+            Transaction::resolve(1, 2),
+            Transaction::chargeback(1, 1),
+            Transaction::deposit(1, 5, amount("1.0")),
+            Transaction::dispute(2, 5),
+        ];
+        transaction_manager.process_batch(transactions.clone()).unwrap();
+        compacted.process_batch(transactions).unwrap();
+        assert!(compacted.accounts().map(ClientAccount::summary).eq(transaction_manager.accounts().map(ClientAccount::summary)));
+        assert_eq!(compacted.held(1), Some(amount("0.0")));
+        assert!(compacted.account(1).unwrap().locked);
+        assert_eq!(compacted.ignored(), [
+            IgnoredTransaction { tx: 5, client: 1, reason: IgnoreReason::DuplicateTransaction },
+            IgnoredTransaction { tx: 5, client: 2, reason: IgnoreReason::AccountLocked },
+        ]);
+    }
+
+    #[test]
+    fn new_clients_beyond_max_clients_ignored() {
+        let mut transaction_manager = TransactionManager::new().with_max_clients(Some(1));
+        transaction_manager.process_transaction(Transaction::deposit(1, 1, amount("3.0"))).unwrap();
+        transaction_manager.process_transaction(Transaction::deposit(2, 2, amount("1.0"))).unwrap();
+        transaction_manager.process_transaction(Transaction::dispute(2, 2)).unwrap();
+        transaction_manager.process_transaction(Transaction::withdrawal(1, 3, amount("1.0"))).unwrap();
+        assert_eq!(transaction_manager.ignored(), &[
+            IgnoredTransaction { tx: 2, client: 2, reason: IgnoreReason::ClientLimitReached },
+            IgnoredTransaction { tx: 2, client: 2, reason: IgnoreReason::ClientLimitReached },
+        ]);
+        assert!(!transaction_manager.has_client(2));
+        assert_eq!(transaction_manager.available(1), Some(amount("2.0")));
+    }
+
+    #[test]
+    fn partial_chargeback_of_whole_disputed_amount() {
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.process_transaction(Transaction::deposit(1, 1, amount("10.0"))).unwrap();
+        transaction_manager.process_transaction(Transaction::partial_dispute(1, 1, amount("4.0"))).unwrap();
+        transaction_manager.process_transaction(Transaction::partial_chargeback(1, 1, amount("4.0"))).unwrap();
+        let client_account = &transaction_manager.client_account_index[&1];
+        assert!(client_account.locked);
+        assert_eq!(client_account.available, amount("6.0"));
+        assert_eq!(client_account.held, amount("0.0"));
+        assert_eq!(client_account.transaction_index.len(), 1);
+    }
+
+    #[test]
+    fn excessive_or_zero_portion_is_ignored() {
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.process_transaction(Transaction::deposit(1, 1, amount("10.0"))).unwrap();
+        assert_eq!(
+            transaction_manager.process_transaction(Transaction::partial_dispute(1, 1, amount("10.5"))).unwrap(),
+            ProcessedOutcome::Ignored(IgnoreReason::ExcessiveAmount),
+        );
+        transaction_manager.process_transaction(Transaction::partial_dispute(1, 1, amount("5.0"))).unwrap();
+        assert_eq!(
+            transaction_manager.process_transaction(Transaction::partial_chargeback(1, 1, amount("6.0"))).unwrap(),
+            ProcessedOutcome::Ignored(IgnoreReason::ExcessiveAmount),
+        );
+        assert_eq!(
+            transaction_manager.process_transaction(Transaction::partial_resolve(1, 1, amount("0.0"))).unwrap(),
+            ProcessedOutcome::Ignored(IgnoreReason::ZeroAmount),
+        );
+        assert_eq!(transaction_manager.available(1), Some(amount("5.0")));
+        assert_eq!(transaction_manager.held(1), Some(amount("5.0")));
+        assert!(!transaction_manager.client_account_index[&1].locked);
+    }
+
     #[test]
     fn chargeback_undisputed_tx_is_ignored() {
         let mut transaction_manager = TransactionManager::new();
         transaction_manager.process_transaction(
-            Transaction::new(TransactionType::Deposit { amount: 10.0 }, 1, 1)
+            Transaction::new(TransactionType::Deposit { amount: amount("10.0") }, 1, 1)
         ).unwrap();
         transaction_manager.process_transaction(
-            Transaction::new(TransactionType::Chargeback, 1, 1)
+            Transaction::new(TransactionType::Chargeback { amount: None }, 1, 1)
         ).unwrap();
         assert_eq!(transaction_manager.client_account_index.len(), 1);
         let client_account = transaction_manager.client_account_index.get(&1).unwrap();
-        assert_eq!(client_account.locked, false);
-        assert_eq!(client_account.available, 10.);
-        assert_eq!(client_account.held, 0.);
+        assert!(!client_account.locked);
+        assert_eq!(client_account.available, amount("10.0"));
+        assert_eq!(client_account.held, amount("0.0"));
         assert_eq!(client_account.transaction_index.len(), 1);
         assert_eq!(client_account.client, 1);
     }
@@ -351,41 +2070,292 @@ mod tests {
     fn chargeback_withdrawn_amount() {
         let mut transaction_manager = TransactionManager::new();
         transaction_manager.process_transaction(
-            Transaction::new(TransactionType::Deposit { amount: 10.0 }, 1, 1)
+            Transaction::new(TransactionType::Deposit { amount: amount("10.0") }, 1, 1)
         ).unwrap();
         transaction_manager.process_transaction(
-            Transaction::new(TransactionType::Withdrawal { amount: 10.0 }, 1, 2)
+            Transaction::new(TransactionType::Withdrawal { amount: amount("10.0") }, 1, 2)
         ).unwrap();
         transaction_manager.process_transaction(
-            Transaction::new(TransactionType::Dispute, 1, 1)
+            Transaction::new(TransactionType::Dispute { amount: None }, 1, 1)
         ).unwrap();
         transaction_manager.process_transaction(
-            Transaction::new(TransactionType::Chargeback, 1, 1)
+            Transaction::new(TransactionType::Chargeback { amount: None }, 1, 1)
         ).unwrap();
         assert_eq!(transaction_manager.client_account_index.len(), 1);
         let client_account = transaction_manager.client_account_index.get(&1).unwrap();
-        assert_eq!(client_account.locked, true);
-        assert_eq!(client_account.available, -10.);
-        assert_eq!(client_account.held, 0.);
+        assert!(client_account.locked);
+        assert_eq!(client_account.available, amount("-10.0"));
+        assert_eq!(client_account.held, amount("0.0"));
         assert_eq!(client_account.transaction_index.len(), 2);
         assert_eq!(client_account.client, 1);
     }
 
     #[test]
-    #[should_panic]
-    fn deposit_locked_account_panics() {
+    fn chargeback_withdrawn_amount_leaves_negative_available_funds() {
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.process_transaction(Transaction::deposit(1, 1, amount("10.0"))).unwrap();
+        transaction_manager.process_transaction(Transaction::withdrawal(1, 2, amount("10.0"))).unwrap();
+        transaction_manager.process_transaction(Transaction::dispute(1, 1)).unwrap();
+        transaction_manager.process_transaction(Transaction::chargeback(1, 1)).unwrap();
+        let client_account = transaction_manager.client_account_index.get(&1).unwrap();
+        assert!(client_account.locked);
+        assert_eq!(client_account.available, amount("-10.0000"));
+        assert_eq!(client_account.held, Amount::ZERO);
+        assert_eq!(client_account.total(), Some(amount("-10.0000")));
+        assert!(transaction_manager.ignored().is_empty());
+    }
+
+    #[test]
+    fn resolve_disputed_withdrawal() {
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Deposit { amount: amount("10.0") }, 1, 1)
+        ).unwrap();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Withdrawal { amount: amount("4.0") }, 1, 2)
+        ).unwrap();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Dispute { amount: None }, 1, 2)
+        ).unwrap();
+        let client_account = transaction_manager.client_account_index.get(&1).unwrap();
+        assert!(!client_account.locked);
+        assert_eq!(client_account.available, amount("6.0"));
+        assert_eq!(client_account.held, amount("4.0"));
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Resolve { amount: None }, 1, 2)
+        ).unwrap();
+        let client_account = transaction_manager.client_account_index.get(&1).unwrap();
+        assert!(!client_account.locked);
+        assert_eq!(client_account.available, amount("6.0"));
+        assert_eq!(client_account.held, amount("0.0"));
+        assert_eq!(client_account.transaction_index.len(), 2);
+    }
+
+    #[test]
+    fn chargeback_disputed_withdrawal() {
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Deposit { amount: amount("10.0") }, 1, 1)
+        ).unwrap();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Withdrawal { amount: amount("4.0") }, 1, 2)
+        ).unwrap();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Dispute { amount: None }, 1, 2)
+        ).unwrap();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Chargeback { amount: None }, 1, 2)
+        ).unwrap();
+        let client_account = transaction_manager.client_account_index.get(&1).unwrap();
+        assert!(client_account.locked);
+        assert_eq!(client_account.available, amount("10.0"));
+        assert_eq!(client_account.held, amount("0.0"));
+        assert_eq!(client_account.transaction_index.len(), 2);
+    }
+
+    #[test]
+    fn dispute_withdrawal_of_drained_account() {
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.process_transaction(Transaction::deposit(1, 1, amount("10.0"))).unwrap();
+        transaction_manager.process_transaction(Transaction::withdrawal(1, 2, amount("10.0"))).unwrap();
+        assert_eq!(transaction_manager.process_transaction(Transaction::dispute(1, 2)).unwrap(), ProcessedOutcome::Applied);
+        let client_account = transaction_manager.client_account_index.get(&1).unwrap();
+        assert_eq!(client_account.available, Amount::ZERO);
+        assert_eq!(client_account.held, amount("10.0"));
+        assert_eq!(client_account.total(), Some(amount("10.0")));
+        transaction_manager.process_transaction(Transaction::chargeback(1, 2)).unwrap();
+        let client_account = transaction_manager.client_account_index.get(&1).unwrap();
+        assert_eq!(client_account.available, amount("10.0"));
+        assert_eq!(client_account.held, Amount::ZERO);
+    }
+
+    #[test]
+    fn resolve_all_disputes_never_leaves_negative_held_funds() {
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Deposit { amount: amount("0.7") }, 1, 1)
+        ).unwrap();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Deposit { amount: amount("0.1") }, 1, 2)
+        ).unwrap();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Dispute { amount: None }, 1, 1)
+        ).unwrap();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Dispute { amount: None }, 1, 2)
+        ).unwrap();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Resolve { amount: None }, 1, 1)
+        ).unwrap();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Chargeback { amount: None }, 1, 2)
+        ).unwrap();
+        let client_account = transaction_manager.client_account_index.get(&1).unwrap();
+        assert!(client_account.locked);
+        assert_eq!(client_account.held, amount("0.0"));
+    }
+
+    #[test]
+    fn sequence_numbers_follow_input_order() {
+        let mut transaction_manager = TransactionManager::new();
+        assert_eq!(Transaction::deposit(1, 1, amount("1.0")).seq(), 0);
+        transaction_manager.process_batch(vec![
+            Transaction::deposit(2, 3, amount("1.0")),
+            Transaction::withdrawal(1, 1, amount("1.0")),
+            Transaction::deposit(1, 2, amount("1.0")),
+            Transaction::dispute(2, 3),
+            Transaction::deposit(2, 1, amount("1.0")),
+        ]).unwrap();
+        let transaction_index = &transaction_manager.client_account_index[&2].transaction_index;
+        assert_eq!(transaction_index[&3].seq(), 1);
+        assert_eq!(transaction_manager.client_account_index[&1].transaction_index[&2].seq(), 3);
+        let mut transactions: Vec<_> = transaction_manager.client_account_index.values()
+            .flat_map(|client_account| client_account.transaction_index.values())
+            .collect();
+        transactions.sort_by_key(|transaction| transaction.seq());
+        assert_eq!(transactions.iter().map(|transaction| transaction.tx()).collect::<Vec<_>>(), [3, 2]);
+    }
+
+    #[test]
+    fn process_batch() {
+        let mut transaction_manager = TransactionManager::new();
+        let report = transaction_manager.process_batch(vec![
+            Transaction::deposit(1, 1, amount("10.0")),
+            Transaction::withdrawal(1, 2, amount("4.0")),
+            Transaction::withdrawal(1, 3, amount("7.0")),
+            Transaction::dispute(1, 1),
+            Transaction::chargeback(1, 1),
+            Transaction::deposit(1, 4, amount("1.0")),
+        ]).unwrap();
+        assert_eq!(report, BatchReport { applied: 4, ignored: 2 });
+        assert_eq!(transaction_manager.processed(), 6);
+        assert_eq!(transaction_manager.available(1), Some(amount("-4.0")));
+        assert!(transaction_manager.client_account_index[&1].locked);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.process_transaction(Transaction::deposit(1, 1, amount("10.5"))).unwrap();
+        transaction_manager.process_transaction(Transaction::deposit(1, 2, amount("2.25"))).unwrap();
+        transaction_manager.process_transaction(Transaction::dispute(1, 2)).unwrap();
+        transaction_manager.process_transaction(Transaction::withdrawal(2, 3, amount("1.0"))).unwrap();
+        let mut snapshot = vec![];
+        transaction_manager.save(&mut snapshot).unwrap();
+
+        let mut transaction_manager = TransactionManager::load(snapshot.as_slice()).unwrap();
+        assert_eq!(transaction_manager.processed(), 4);
+        assert_eq!(transaction_manager.ignored(), &[
+            IgnoredTransaction { tx: 3, client: 2, reason: IgnoreReason::InsufficientFunds },
+        ]);
+        let client_account = transaction_manager.client_account_index.get(&1).unwrap();
+        assert_eq!(client_account.available, amount("10.5"));
+        assert_eq!(client_account.held, amount("2.25"));
+        assert_eq!(client_account.tx_count, 2);
+
+        // The transactions of the snapshot can still be disputed.
+        transaction_manager.process_transaction(Transaction::dispute(1, 1)).unwrap();
+        transaction_manager.process_transaction(Transaction::chargeback(1, 2)).unwrap();
+        let client_account = transaction_manager.client_account_index.get(&1).unwrap();
+        assert!(client_account.locked);
+        assert_eq!(client_account.available, Amount::ZERO);
+        assert_eq!(client_account.held, amount("10.5"));
+    }
+
+    #[test]
+    fn replay_with_tx_log() {
+        let transactions = || vec![
+            Transaction::deposit(1, 1, amount("10.0")),
+            Transaction::withdrawal(1, 2, amount("4.0")),
+            Transaction::deposit(2, 3, amount("3.0")),
+            Transaction::dispute(2, 3),
+        ];
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.process_batch(transactions()).unwrap();
+        let (mut snapshot, mut tx_log) = (vec![], vec![]);
+        transaction_manager.save(&mut snapshot).unwrap();
+        transaction_manager.save_tx_log(&mut tx_log).unwrap();
+
+        let mut transaction_manager = TransactionManager::load(snapshot.as_slice()).unwrap()
+            .with_tx_log(tx_log.as_slice()).unwrap();
+        let report = transaction_manager.process_batch(transactions()).unwrap();
+        assert_eq!(report, BatchReport { applied: 0, ignored: 4 });
+        assert_eq!(transaction_manager.available(1), Some(amount("6.0")));
+        assert_eq!(transaction_manager.available(2), Some(amount("0.0")));
+        assert_eq!(transaction_manager.held(2), Some(amount("3.0")));
+
+        // The log alone skips the deposits and withdrawals.
+        let mut transaction_manager = TransactionManager::new().with_tx_log(tx_log.as_slice()).unwrap();
+        transaction_manager.process_transaction(Transaction::deposit(1, 1, amount("10.0"))).unwrap();
+        assert_eq!(transaction_manager.ignored()[0].reason, IgnoreReason::DuplicateTransaction);
+        assert!(TransactionManager::new().with_tx_log("[1,".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn load_invalid_snapshot_fails() {
+        assert!(TransactionManager::load("{".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn chargeback_locked_account_is_ignored() {
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Deposit { amount: amount("10.0") }, 1, 1)
+        ).unwrap();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Deposit { amount: amount("5.0") }, 1, 2)
+        ).unwrap();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Dispute { amount: None }, 1, 1)
+        ).unwrap();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Dispute { amount: None }, 1, 2)
+        ).unwrap();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Chargeback { amount: None }, 1, 1)
+        ).unwrap();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Chargeback { amount: None }, 1, 1)
+        ).unwrap();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Chargeback { amount: None }, 1, 2)
+        ).unwrap();
+        transaction_manager.process_transaction(
+            Transaction::new(TransactionType::Resolve { amount: None }, 1, 2)
+        ).unwrap();
+        let client_account = transaction_manager.client_account_index.get(&1).unwrap();
+        assert!(client_account.locked);
+        assert_eq!(client_account.available, amount("0.0"));
+        assert_eq!(client_account.held, amount("5.0"));
+        assert_eq!(transaction_manager.ignored(), &[
+            IgnoredTransaction { tx: 1, client: 1, reason: IgnoreReason::AlreadyChargedBack },
+            IgnoredTransaction { tx: 2, client: 1, reason: IgnoreReason::AccountLocked },
+            IgnoredTransaction { tx: 2, client: 1, reason: IgnoreReason::AccountLocked },
+        ]);
+    }
+
+    #[test]
+    fn deposit_locked_account_is_ignored() {
         let mut transaction_manager = TransactionManager::new();
         transaction_manager.process_transaction(
-            Transaction::new(TransactionType::Deposit { amount: 10.0 }, 1, 1)
+            Transaction::new(TransactionType::Deposit { amount: amount("10.0") }, 1, 1)
         ).unwrap();
         transaction_manager.process_transaction(
-            Transaction::new(TransactionType::Dispute, 1, 1)
+            Transaction::new(TransactionType::Dispute { amount: None }, 1, 1)
         ).unwrap();
         transaction_manager.process_transaction(
-            Transaction::new(TransactionType::Chargeback, 1, 1)
+            Transaction::new(TransactionType::Chargeback { amount: None }, 1, 1)
         ).unwrap();
         transaction_manager.process_transaction(
-            Transaction::new(TransactionType::Deposit { amount: 10.0 }, 1, 2)
+            Transaction::new(TransactionType::Deposit { amount: amount("10.0") }, 1, 2)
         ).unwrap();
+        let client_account = transaction_manager.client_account_index.get(&1).unwrap();
+        assert!(client_account.locked);
+        assert_eq!(client_account.available, amount("0.0"));
+        assert_eq!(client_account.held, amount("0.0"));
+        assert_eq!(client_account.transaction_index.len(), 1);
+        assert_eq!(transaction_manager.ignored(), &[
+            IgnoredTransaction { tx: 2, client: 1, reason: IgnoreReason::AccountLocked },
+        ]);
     }
 }
\ No newline at end of file
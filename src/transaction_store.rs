@@ -0,0 +1,52 @@
+//! # Stores of the transactions of the client accounts.
+
+use std::collections::HashMap;
+use crate::error::EngineError;
+use crate::transaction_manager::{ClientId, Transaction, TransactionState};
+
+/// A store of the disputable transactions of a client account, indexed by transaction id.
+///
+/// The transactions are kept in memory in a `HashMap` by default. Other stores, e.g. on disk with
+/// the `redb-store` feature, can be used for files whose transactions don't fit in memory.
+pub trait TransactionStore {
+    /// Create an empty store for the transactions of the given client, sharing the resources of
+    /// this store, e.g. its database.
+    fn for_client(&self, client: ClientId) -> Self where Self: Sized;
+
+    fn insert(&mut self, transaction: Transaction) -> Result<(), EngineError>;
+
+    fn get(&self, tx: u32) -> Result<Option<Transaction>, EngineError>;
+
+    /// Update the state of the transaction with the given transaction id, if it exists.
+    fn set_state(&mut self, tx: u32, state: TransactionState) -> Result<(), EngineError>;
+
+    fn remove(&mut self, tx: u32) -> Result<(), EngineError>;
+}
+
+/// In-memory store of the transactions.
+impl TransactionStore for HashMap<u32, Transaction> {
+    fn for_client(&self, _client: ClientId) -> HashMap<u32, Transaction> {
+        HashMap::new()
+    }
+
+    fn insert(&mut self, transaction: Transaction) -> Result<(), EngineError> {
+        HashMap::insert(self, transaction.tx(), transaction);
+        Ok(())
+    }
+
+    fn get(&self, tx: u32) -> Result<Option<Transaction>, EngineError> {
+        Ok(HashMap::get(self, &tx).cloned())
+    }
+
+    fn set_state(&mut self, tx: u32, state: TransactionState) -> Result<(), EngineError> {
+        if let Some(transaction) = HashMap::get_mut(self, &tx) {
+            transaction.set_state(state);
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, tx: u32) -> Result<(), EngineError> {
+        HashMap::remove(self, &tx);
+        Ok(())
+    }
+}
@@ -0,0 +1,44 @@
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+#[test]
+fn write_output_to_file() {
+    let output = std::env::temp_dir().join(format!("payments-engine-rs-{}.csv", std::process::id()));
+    let status = Command::new(env!("CARGO_BIN_EXE_payments-engine-rs"))
+        .args([concat!(env!("CARGO_MANIFEST_DIR"), "/sample.csv"), "-o"])
+        .arg(&output)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let contents = fs::read_to_string(&output).unwrap();
+    fs::remove_file(&output).unwrap();
+    assert_eq!(contents, "client,available,held,total,locked\n1,-1.0000,0.0000,-1.0000,true\n");
+}
+
+#[test]
+fn log_ignored_transactions() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_payments-engine-rs"))
+        .env("RUST_LOG", "debug")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"type,client,tx,amount\ndeposit,1,1,1.0\nwithdrawal,1,2,2.0\n").unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(stderr, "[DEBUG] Ignored transaction 2 of client 1: InsufficientFunds\n");
+}
+
+#[test]
+fn print_dispute_stats() {
+    let output = Command::new(env!("CARGO_BIN_EXE_payments-engine-rs"))
+        .args([concat!(env!("CARGO_MANIFEST_DIR"), "/sample.csv"), "--stats"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(stderr, "processed 8 transactions for 1 clients, 0 ignored, 0.0 held, 2 disputes opened, 1 resolved, 1 charged back\n");
+}